@@ -1,4 +1,7 @@
 <?xml version="1.0" encoding="UTF-8"?>
 <tileset version="1.5" tiledversion="1.7.0" name="entities" tilewidth="32" tileheight="32" tilecount="256" columns="16" objectalignment="bottom">
  <image source="../sprites/entities.png" width="512" height="512"/>
+ <tile id="0" type="tomato"/>
+ <tile id="16" type="checkpoint"/>
+ <tile id="32" type="platform"/>
 </tileset>
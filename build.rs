@@ -4,6 +4,7 @@ use std::io::Write;
 use std::fs::{self, File};
 use std::env;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
 use image::GenericImageView;
 use chrono::Datelike;
@@ -38,13 +39,136 @@ def_impl!(DataDef);
 #[derive(Debug)]
 struct LevelDef {
     offset: usize,
-    width: u8,
-    height: u8,
+    back: Option<usize>,
+    front: Option<usize>,
+    width: u16,
+    height: u16,
     start_pos: Field,
+    tileset: usize,
+    outside: [u8; 4],
+    gems: u16,
+    scroll: Field,
+    scroll_speed: i32,
 }
 
 def_impl!(LevelDef);
 
+#[derive(Debug)]
+struct LevelInfo {
+    name: Field,
+    world: u8,
+    number: u8,
+    first_room: usize,
+    rooms: usize,
+    music: u8,
+    sky: u32,
+}
+
+def_impl!(LevelInfo);
+
+#[derive(Debug)]
+struct Tileset {
+    tiles: usize,
+    len: usize,
+    pal: usize,
+}
+
+def_impl!(Tileset);
+
+#[derive(Debug)]
+struct TileAnim {
+    tile: u16,
+    frames: usize,
+    len: usize,
+    period: u16,
+}
+
+def_impl!(TileAnim);
+
+#[derive(Debug)]
+struct AnimFrame {
+    tile: u16,
+    end: u16,
+}
+
+def_impl!(AnimFrame);
+
+#[derive(Debug)]
+struct TileTop {
+    tile: u16,
+    top: u16,
+}
+
+def_impl!(TileTop);
+
+#[derive(Debug)]
+struct BgImage {
+    offset: usize,
+    pal: usize,
+    width: u16,
+    height: u16,
+}
+
+def_impl!(BgImage);
+
+#[derive(Debug)]
+struct BgLayer {
+    image: usize,
+    scroll: Field,
+    speed: Field,
+    repeat: Field,
+    anchor: i32,
+}
+
+def_impl!(BgLayer);
+
+#[derive(Debug)]
+struct MenuEntry {
+    text: Field,
+    level: usize,
+    room: usize,
+}
+
+def_impl!(MenuEntry);
+
+#[derive(Debug)]
+struct Warp {
+    kind: Field,
+    pos: Field,
+    size: Field,
+    room: usize,
+    entry: Field,
+}
+
+def_impl!(Warp);
+
+#[derive(Debug)]
+struct Node {
+    pos: Field,
+    level: Option<usize>,
+}
+
+def_impl!(Node);
+
+#[derive(Debug)]
+struct Route {
+    from: usize,
+    to: usize,
+    unlock: Option<usize>,
+}
+
+def_impl!(Route);
+
+#[derive(Debug)]
+struct CameraZone {
+    pos: Field,
+    size: Field,
+    scroll_up: bool,
+    scroll_down: bool,
+}
+
+def_impl!(CameraZone);
+
 struct Field(String);
 impl std::fmt::Debug for Field {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -61,11 +185,64 @@ fn main() {
     let mut data = vec![];
     let mut pal = vec![];
 
-    let img = image::open("assets/fg/fries.png").unwrap().to_rgba8();
-    embed_fg(&img, 16, &mut data, &mut pal).write(&mut f, "BLOCKS");
+    let manifest: serde_json::Value = serde_json::from_slice(&fs::read("assets/manifest.json").unwrap()).unwrap();
+    let mut levels = vec![];
+    let mut rooms = vec![];
+    let mut menu = vec![];
+    let mut backgrounds = vec![];
+    let mut tilesets = vec![];
+    let mut room_tilesets = vec![];
+    let mut room_levels = vec![];
+    let mut layers = vec![];
+    let mut layer_list = vec![];
+    let mut music = vec![];
+    for (w, world) in manifest["worlds"].as_array().unwrap().iter().enumerate() {
+        for (l, level) in world["levels"].as_array().unwrap().iter().enumerate() {
+            let name = level["name"].as_str().unwrap();
+            let first_room = rooms.len();
+            for (r, room) in level["rooms"].as_array().unwrap().iter().enumerate() {
+                let text = if r == 0 {
+                    format!("{}-{}-{} {}", w + 1, l + 1, r + 1, name)
+                } else {
+                    format!("{}-{}-{}", w + 1, l + 1, r + 1)
+                };
+                menu.push(MenuEntry {
+                    text: Field(format!("b{:?}", text)),
+                    level: levels.len(),
+                    room: rooms.len(),
+                });
+                rooms.push(room.as_str().unwrap().to_string());
+                room_levels.push(levels.len());
+                room_tilesets.push(level["tileset"].as_str().expect(&format!("level {} has no tileset", name)).to_string());
+            }
+            levels.push(LevelInfo {
+                name: Field(format!("b{:?}", name)),
+                world: w as u8 + 1,
+                number: l as u8 + 1,
+                first_room,
+                rooms: rooms.len() - first_room,
+                music: intern(&mut music, level["music"].as_str().unwrap()) as u8,
+                sky: sky_color(&level["sky"]),
+            });
+            layer_list.push(layers.len());
+            layers.extend(bg_layers(&level["background"], &mut backgrounds));
+        }
+    }
+
+    BgImage::write_start(&mut f, "BACKGROUNDS", backgrounds.len());
+    for i in backgrounds.iter() {
+        let img = image::open(format!("assets/bg/{}.png", i)).unwrap().to_rgba8();
+        embed_bg(&img, &mut data, &mut pal).write_entry(&mut f);
+    }
+    BgImage::write_end(&mut f);
 
-    let img = image::open("assets/bg/hills.png").unwrap().to_rgba8();
-    embed_bg(&img, &mut data, &mut pal).write(&mut f, "BG");
+    layer_list.push(layers.len());
+    BgLayer::write_start(&mut f, "BG_LAYERS", layers.len());
+    for i in layers.iter() {
+        i.write_entry(&mut f);
+    }
+    BgLayer::write_end(&mut f);
+    writeln!(f, "pub static BG_LAYER_OFFSET: [usize; {}] = {:?};", layer_list.len(), layer_list).unwrap();
 
     let img = image::open("assets/sprites/toothpaste.png").unwrap().to_rgba8();
     embed_fg(&img, 32, &mut data, &mut pal).write(&mut f, "TOOTHPASTE");
@@ -85,22 +262,107 @@ fn main() {
     let mut ent = String::new();
     let mut ent_len = 0;
 
-    let count = 3;
+    let mut item_list = vec![];
+    let mut item = String::new();
+    let mut item_len = 0;
+
+    let mut map_size = 0;
+
+    let maps = rooms.iter()
+        .map(|c| serde_json::from_slice(&fs::read(format!("assets/maps/{}.json", c)).unwrap()).unwrap())
+        .collect::<Vec<serde_json::Value>>();
+    let mut warps = vec![];
+    let mut warp_list = vec![];
+    let mut zones = vec![];
+    let mut zone_list = vec![];
+    let mut path = vec![];
+    let mut path_list = vec![];
+    let mut collectibles = vec![];
+    let mut collectible_list = vec![];
 
-    writeln!(f, "pub static LEVEL_COUNT: usize = {};", count);
+    embed_overworld(&mut f, &levels);
 
-    LevelDef::write_start(&mut f, "MAPS", count);
-    for i in 0..count {
-        let level = serde_json::from_slice(&fs::read(format!("assets/maps/L0A{}.json", i)).unwrap()).unwrap();
-        embed_map(&level, &mut data).write_entry(&mut f);
-        ent_list.push(embed_entities(&level, &mut ent, &mut ent_len));
+    writeln!(f, "pub const LEVEL_COUNT: usize = {};", levels.len()).unwrap();
+    writeln!(f, "pub const ROOM_COUNT: usize = {};", rooms.len()).unwrap();
+    LevelInfo::write_start(&mut f, "LEVELS", levels.len());
+    for i in levels.iter() {
+        i.write_entry(&mut f);
+    }
+    LevelInfo::write_end(&mut f);
+
+    MenuEntry::write_start(&mut f, "TITLE_MENU", menu.len());
+    for i in menu.iter() {
+        i.write_entry(&mut f);
+    }
+    MenuEntry::write_end(&mut f);
+
+    let mut map_tilesets = vec![];
+    LevelDef::write_start(&mut f, "MAPS", rooms.len());
+    for (i, (room, level)) in rooms.iter().zip(maps.iter()).enumerate() {
+        let tileset = property(level, "tileset").and_then(|c| c.as_str()).unwrap_or(&room_tilesets[i]);
+        let tileset = intern(&mut tilesets, tileset);
+        map_tilesets.push(tileset);
+        check_tile_layers(room, level);
+        let map = embed_map(level, tileset, &mut data);
+        let area = map.width as usize * map.height as usize;
+        if area > MAX_MAP_SIZE {
+            panic!("{}.json is {}x{} blocks, over the limit of {} blocks", room, map.width, map.height, MAX_MAP_SIZE);
+        }
+        map_size = map_size.max(area);
+        map.write_entry(&mut f);
+        ent_list.push(embed_entities(level, &mut ent, &mut ent_len));
+        item_list.push(embed_items(level, &mut item, &mut item_len));
+        warp_list.push(warps.len());
+        let info = &levels[room_levels[i]];
+        let level_rooms = info.first_room..info.first_room + info.rooms;
+        warps.extend(embed_warps(room, &level, &rooms, &maps, level_rooms));
+        zone_list.push(zones.len());
+        zones.extend(embed_camera_zones(level));
+        path_list.push(path.len());
+        path.extend(embed_scroll_path(level));
+        collectible_list.push(collectibles.len());
+        collectibles.extend(embed_collectibles(level));
     }
     LevelDef::write_end(&mut f);
 
+    embed_tilesets(&mut f, &tilesets, &mut data, &mut pal);
+    embed_tile_anims(&mut f, &tilesets, &maps, &map_tilesets);
+    embed_tile_tops(&mut f, &tilesets, &maps, &map_tilesets);
+
     ent_list.push(ent_len);
+    item_list.push(item_len);
+
+    writeln!(f, "pub const MAP_SIZE: usize = {};", map_size).unwrap();
 
     write!(f, "pub static ENTITY_LIST: [EntityEntry; {}] = [{}];", ent_len, ent);
     write!(f, "pub static ENTITY_OFFSET: [usize; {}] = {:?};", ent_list.len(), ent_list);
+    write!(f, "pub static BLOCK_ITEMS: [BlockItem; {}] = [{}];", item_len, item).unwrap();
+    warp_list.push(warps.len());
+    Warp::write_start(&mut f, "WARPS", warps.len());
+    for i in warps.iter() {
+        i.write_entry(&mut f);
+    }
+    Warp::write_end(&mut f);
+    writeln!(f, "pub static WARP_OFFSET: [usize; {}] = {:?};", warp_list.len(), warp_list).unwrap();
+
+    zone_list.push(zones.len());
+    CameraZone::write_start(&mut f, "CAMERA_ZONES", zones.len());
+    for i in zones.iter() {
+        i.write_entry(&mut f);
+    }
+    CameraZone::write_end(&mut f);
+    writeln!(f, "pub static CAMERA_ZONE_OFFSET: [usize; {}] = {:?};", zone_list.len(), zone_list).unwrap();
+
+    path_list.push(path.len());
+    writeln!(f, "pub static SCROLL_PATH: [Vec2<i32>; {}] = [{}];", path.len(), path.join(", ")).unwrap();
+    writeln!(f, "pub static SCROLL_PATH_OFFSET: [usize; {}] = {:?};", path_list.len(), path_list).unwrap();
+
+    collectible_list.push(collectibles.len());
+    writeln!(f, "pub const COLLECTIBLE_COUNT: usize = {};", collectibles.len()).unwrap();
+    writeln!(f, "pub static COLLECTIBLES: [u32; {}] = {:?};", collectibles.len(), collectibles).unwrap();
+    writeln!(f, "pub static COLLECTIBLE_OFFSET: [usize; {}] = {:?};", collectible_list.len(), collectible_list).unwrap();
+
+    write!(f, "pub static BLOCK_ITEM_OFFSET: [usize; {}] = {:?};", item_list.len(), item_list).unwrap();
 
     let comp = lz4::block::compress(&data, lz4::block::CompressionMode::HIGHCOMPRESSION(12).into(), false).unwrap();
 
@@ -119,37 +381,188 @@ fn main() {
 
 }
 
-fn embed_map(level: &serde_json::Value, data: &mut Vec<u8>) -> LevelDef {
+// Nodes (optionally entering a level given as "world-number") and the paths
+// between them. A path opens once the level at its `from` node is complete,
+// or the level named by its own `unlock` property.
+fn embed_overworld(mut f: impl io::Write, levels: &[LevelInfo]) {
+    let map: serde_json::Value = serde_json::from_slice(&fs::read("assets/maps/overworld.json").unwrap()).unwrap();
+    let find_level = |name: &serde_json::Value| -> usize {
+        let name = name.as_str().unwrap();
+        levels.iter()
+            .position(|c| format!("{}-{}", c.world, c.number) == name)
+            .unwrap_or_else(|| panic!("overworld refers to unknown level {}", name))
+    };
+    let objects = objects(&map);
+    let nodes = objects.iter().filter(|c| c["type"] == "node").collect::<Vec<_>>();
+    let find_node = |name: &serde_json::Value| -> usize {
+        nodes.iter()
+            .position(|c| &c["name"] == name)
+            .unwrap_or_else(|| panic!("overworld path refers to unknown node {}", name))
+    };
+    Node::write_start(&mut f, "NODES", nodes.len());
+    for i in nodes.iter() {
+        Node {
+            pos: Field(format!("vec2({}, {})", i["x"].as_f64().unwrap() as i64, i["y"].as_f64().unwrap() as i64)),
+            level: property(i, "level").map(find_level),
+        }.write_entry(&mut f);
+    }
+    Node::write_end(&mut f);
+    let routes = objects.iter().filter(|c| c["type"] == "path").collect::<Vec<_>>();
+    Route::write_start(&mut f, "ROUTES", routes.len());
+    for i in routes.iter() {
+        let from = find_node(property(i, "from").expect("overworld path without `from`"));
+        let to = find_node(property(i, "to").expect("overworld path without `to`"));
+        Route {
+            from,
+            to,
+            unlock: property(i, "unlock").map(find_level).or(property(nodes[from], "level").map(find_level)),
+        }.write_entry(&mut f);
+    }
+    Route::write_end(&mut f);
+    let start = nodes.iter().position(|c| c["name"] == "start").unwrap_or(0);
+    writeln!(f, "pub const START_NODE: usize = {};", start).unwrap();
+}
+
+// Index of `name` in `list`, adding it if it's not there yet.
+fn intern(list: &mut Vec<String>, name: &str) -> usize {
+    list.iter().position(|c| c == name).unwrap_or_else(|| {
+        list.push(name.to_string());
+        list.len() - 1
+    })
+}
+
+// All layers of a map, with group layers flattened.
+fn layers(level: &serde_json::Value) -> Vec<&serde_json::Value> {
+    let mut out = vec![];
+    for i in level["layers"].as_array().unwrap() {
+        if i["type"] == "group" {
+            out.extend(layers(i));
+        } else {
+            out.push(i);
+        }
+    }
+    out
+}
+
+fn is_named(layer: &serde_json::Value, names: &[&str]) -> bool {
+    names.iter().any(|n| layer["name"].as_str().unwrap().eq_ignore_ascii_case(n))
+}
+
+fn tile_layer<'a>(level: &'a serde_json::Value, names: &[&str]) -> Option<&'a serde_json::Value> {
+    layers(level).into_iter()
+        .filter(|c| c["type"] == "tilelayer")
+        .find(|c| is_named(c, names))
+}
+
+// Objects from every object layer, in layer order.
+fn objects(level: &serde_json::Value) -> Vec<&serde_json::Value> {
+    layers(level).into_iter()
+        .filter(|c| c["type"] == "objectgroup")
+        .flat_map(|c| c["objects"].as_array().unwrap())
+        .collect()
+}
+
+// Largest map the game can hold, in blocks (e.g. 1024x256).
+const MAX_MAP_SIZE: usize = 1024 * 256;
+
+const BACK_LAYER: &[&str] = &["Back", "Behind"];
+const FRONT_LAYER: &[&str] = &["Front", "Decoration"];
+const MAIN_LAYER: &[&str] = &["FG", "Main", "Collision"];
+
+fn embed_tiles(layer: &serde_json::Value, data: &mut Vec<u8>) -> usize {
     let offset = data.len();
-    let tiles = &level["layers"][0]["data"];
-    for i in tiles.as_array().unwrap().iter() {
-        data.push((i.as_u64().unwrap() - 1) as u8);
-    }
-    let width = level["width"].as_u64().unwrap() as u8;
-    let height = level["height"].as_u64().unwrap() as u8;
-    let entities = &level["layers"][1]["objects"].as_array().unwrap();
-    let list = entities.iter().map(|c| c["gid"].as_u64().unwrap()).collect::<Vec<_>>();
-    let start_pos = entities.iter().find(|c| c["gid"].as_u64().unwrap() & 0xFFF == 0x210).map(|c| {
+    for i in layer["data"].as_array().unwrap().iter() {
+        // Strip the flip flags; gid 0 (no tile) and 1 (the first, empty tile) both become 0
+        data.push((i.as_u64().unwrap() & 0x0FFF_FFFF).saturating_sub(1) as u8);
+    }
+    offset
+}
+
+fn main_layer(level: &serde_json::Value) -> &serde_json::Value {
+    tile_layer(level, MAIN_LAYER).or_else(|| {
+        // Fall back to the only tile layer that isn't explicitly behind or in front
+        layers(level).into_iter()
+            .filter(|c| c["type"] == "tilelayer")
+            .find(|c| !is_named(c, BACK_LAYER) && !is_named(c, FRONT_LAYER))
+    }).expect("map has no main tile layer")
+}
+
+// Only one main, back and front tile layer are drawn; any other tile layer
+// is a mistake rather than something to drop quietly.
+fn check_tile_layers(room: &str, level: &serde_json::Value) {
+    let main = main_layer(level);
+    let mut seen = vec![];
+    for layer in layers(level).into_iter().filter(|c| c["type"] == "tilelayer") {
+        let name = layer["name"].as_str().unwrap();
+        let kind = if std::ptr::eq(layer, main) || is_named(layer, MAIN_LAYER) {
+            "main"
+        } else if is_named(layer, BACK_LAYER) {
+            "back"
+        } else if is_named(layer, FRONT_LAYER) {
+            "front"
+        } else {
+            panic!("{}.json has tile layer {}, which isn't named as one of {:?}, {:?} or {:?}",
+                room, name, MAIN_LAYER, BACK_LAYER, FRONT_LAYER);
+        };
+        if seen.contains(&kind) {
+            panic!("{}.json has more than one {} tile layer, {} is extra", room, kind, name);
+        }
+        seen.push(kind);
+    }
+}
+
+fn start_pos(level: &serde_json::Value) -> Option<Field> {
+    objects(level).into_iter().find(|c| c["gid"].as_u64().unwrap_or(0) & 0xFFF == 0x210).map(|c| {
         Field(format!("vec2({}, {})", c["x"].as_u64().unwrap() * 256, c["y"].as_u64().unwrap() * 256))
-    }).expect(&format!("{:?}", list));
+    })
+}
+
+fn embed_map(level: &serde_json::Value, tileset: usize, data: &mut Vec<u8>) -> LevelDef {
+    let main = main_layer(level);
+    let offset = embed_tiles(main, data);
+    let back = tile_layer(level, BACK_LAYER).map(|c| embed_tiles(c, data));
+    let front = tile_layer(level, FRONT_LAYER).map(|c| embed_tiles(c, data));
+    let width = u16::try_from(level["width"].as_u64().unwrap()).expect("map is too wide");
+    let height = u16::try_from(level["height"].as_u64().unwrap()).expect("map is too tall");
+    let list = objects(level).iter().map(|c| c["gid"].as_u64().unwrap_or(0)).collect::<Vec<_>>();
+    let start_pos = start_pos(level).expect(&format!("{:?}", list));
+    let scroll = match property(level, "scroll").and_then(|c| c.as_str()) {
+        Some("vertical") => "Vertical",
+        Some("auto") => "Auto",
+        _ => "Free",
+    };
+    // Blocks past each edge, empty by default: no walls, open sky, bottomless pit
+    let outside = ["outside_left", "outside_right", "outside_top", "outside_bottom"]
+        .map(|c| property(level, c).and_then(|c| c.as_u64()).unwrap_or(0) as u8);
+    let scroll_speed = property(level, "scroll_speed").and_then(|c| c.as_f64()).unwrap_or(0.5);
     LevelDef {
         offset,
+        back,
+        front,
         width,
         height,
-        start_pos
+        start_pos,
+        tileset,
+        outside,
+        gems: count_gems(level),
+        scroll: Field(format!("ScrollMode::{}", scroll)),
+        scroll_speed: (scroll_speed * 256.0) as i32,
     }
 }
 
 fn embed_entities(level: &serde_json::Value, out: &mut String, len: &mut usize) -> usize {
     use std::fmt::Write;
     let base = *len;
-    let entities = &level["layers"][1]["objects"].as_array().unwrap();
-    for i in entities.iter() {
+    for i in objects(level) {
         // x:u16 y:u16 id
-        let x = (i["x"].as_u64().unwrap() / 0x10) as u8 + 1;
-        let y = (i["y"].as_u64().unwrap() / 0x10) as u8 - 1;
-        let id = match i["gid"].as_u64().unwrap() & 0xFFF {
+        // Tile objects are anchored at their bottom left corner
+        let x = u16::try_from(i["x"].as_u64().unwrap() / 0x10 + 1).expect("object is outside the map");
+        let y = u16::try_from((i["y"].as_u64().unwrap() / 0x10).saturating_sub(1)).expect("object is outside the map");
+        let id = match i["gid"].as_u64().unwrap_or(0) & 0xFFF {
+            // Frames of the entities tileset, which starts at 0x111
             0x111 => 2,
+            0x131 => 5,
+            0x121 => 6,
             //0x45 => 3,
             //0x49 => 4,
             //0x51 => 5,
@@ -164,6 +577,295 @@ fn embed_entities(level: &serde_json::Value, out: &mut String, len: &mut usize)
     base
 }
 
+// Rectangles of type `item` override the contents of the item box under them.
+fn embed_items(level: &serde_json::Value, out: &mut String, len: &mut usize) -> usize {
+    use std::fmt::Write;
+    let base = *len;
+    for i in objects(level).into_iter().filter(|c| c["type"] == "item") {
+        let x = i["x"].as_u64().unwrap() / 0x10;
+        let y = i["y"].as_u64().unwrap() / 0x10;
+        let item = property(i, "item")
+            .and_then(|c| c.as_u64())
+            .expect("item object without an `item` property");
+        writeln!(out, r"BlockItem {{
+            x: {:#02X}, y: {:#02X}, item: {:#02X}
+        }}, ", x, y, item).unwrap();
+        *len += 1;
+    }
+    base
+}
+
+// Gems to be found in a map: loose gem blocks plus boxes holding a gem. Box
+// contents default as in `foreground::action` unless an item object says otherwise.
+fn count_gems(level: &serde_json::Value) -> u16 {
+    const GEM: u64 = 0x10;
+    let width = level["width"].as_u64().unwrap();
+    let items = objects(level).into_iter()
+        .filter(|c| c["type"] == "item")
+        .map(|c| ((c["x"].as_u64().unwrap() / 0x10, c["y"].as_u64().unwrap() / 0x10), property(c, "item").and_then(|c| c.as_u64())))
+        .collect::<HashMap<_,_>>();
+    main_layer(level)["data"].as_array().unwrap().iter().enumerate().filter(|(i, c)| {
+        let pos = (*i as u64 % width, *i as u64 / width);
+        let item = items.get(&pos).copied().flatten();
+        match (c.as_u64().unwrap() & 0x0FFF_FFFF).saturating_sub(1) {
+            GEM => true,
+            0x02 => item.unwrap_or(GEM) == GEM,
+            0x08 => item == Some(GEM),
+            _ => false,
+        }
+    }).count() as u16
+}
+
+// Blocks the player uses up: item boxes and pickups, as sorted block indices,
+// so a level attempt can remember which are gone when a room is reloaded.
+fn embed_collectibles(level: &serde_json::Value) -> Vec<u32> {
+    main_layer(level)["data"].as_array().unwrap().iter().enumerate().filter(|(_, c)| {
+        matches!((c.as_u64().unwrap() & 0x0FFF_FFFF).saturating_sub(1), 0x02 | 0x08 | 0x10..=0x12)
+    }).map(|(i, _)| i as u32).collect()
+}
+
+fn property<'a>(object: &'a serde_json::Value, name: &str) -> Option<&'a serde_json::Value> {
+    object["properties"].as_array()
+        .and_then(|c| c.iter().find(|c| c["name"] == name))
+        .map(|c| &c["value"])
+}
+
+// Bounding box of an object in pixels; tile objects are anchored at the bottom.
+fn object_rect(object: &serde_json::Value) -> (i64, i64, i64, i64) {
+    let (x, y) = (object["x"].as_f64().unwrap() as i64, object["y"].as_f64().unwrap() as i64);
+    let (w, h) = (object["width"].as_f64().unwrap() as i64, object["height"].as_f64().unwrap() as i64);
+    if object["gid"].is_u64() {
+        (x, y - h, w, h)
+    } else {
+        (x, y, w, h)
+    }
+}
+
+// Where a player entering through `object` stands: centered, feet on its bottom edge.
+fn entry_pos(object: &serde_json::Value) -> Field {
+    let (x, y, w, h) = object_rect(object);
+    Field(format!("vec2({}, {})", (x + w / 2) * 256, (y + h) * 256 - 0xF00))
+}
+
+// Doors (Up), pipes (Down) and exits (on touch), each leading to the object
+// named by its `entry` property in the room named by its `target` property.
+// Exit blocks (0x0F) become doors to the start of the next room, or finish
+// the level in its last room. Objects can only lead to rooms of the same
+// level, `level_rooms`.
+fn embed_warps(room: &str, level: &serde_json::Value, rooms: &[String], maps: &[serde_json::Value], level_rooms: std::ops::Range<usize>) -> Vec<Warp> {
+    let mut out = vec![];
+    let index = rooms.iter().position(|c| c == room).unwrap();
+    let next_start = Some(index + 1).filter(|c| level_rooms.contains(c))
+        .and_then(|c| start_pos(&maps[c]))
+        .unwrap_or(Field("vec2(0, 0)".into()));
+    let width = level["width"].as_u64().unwrap();
+    let main = main_layer(level);
+    for (i, tile) in main["data"].as_array().unwrap().iter().enumerate() {
+        if tile.as_u64().unwrap() & 0x0FFF_FFFF != 0x10 { continue; }
+        let (x, y) = (i as u64 % width, i as u64 / width);
+        out.push(Warp {
+            kind: Field("WarpKind::Door".into()),
+            pos: Field(format!("vec2({}, {})", x * 0x1000, y * 0x1000)),
+            size: Field("vec2(0x1000, 0x1000)".into()),
+            room: index + 1,
+            entry: Field(next_start.0.clone()),
+        });
+    }
+    for i in objects(level) {
+        let kind = match i["type"].as_str() {
+            Some("door") => "Door",
+            Some("pipe") => "Pipe",
+            Some("exit") => "Exit",
+            _ => continue,
+        };
+        let target = property(i, "target").and_then(|c| c.as_str())
+            .unwrap_or_else(|| panic!("{} in {} has no `target` property", kind, room));
+        let entry = property(i, "entry").and_then(|c| c.as_str())
+            .unwrap_or_else(|| panic!("{} in {} has no `entry` property", kind, room));
+        let target_room = rooms.iter().position(|c| c == target)
+            .unwrap_or_else(|| panic!("{} in {} leads to unknown room {}", kind, room, target));
+        if !level_rooms.contains(&target_room) {
+            panic!("{} in {} leads to {}, which is in another level", kind, room, target);
+        }
+        let entry = objects(&maps[target_room]).into_iter()
+            .find(|c| c["name"] == entry)
+            .unwrap_or_else(|| panic!("{} in {} leads to missing entry {} in {}", kind, room, entry, target));
+        let (x, y, w, h) = object_rect(i);
+        out.push(Warp {
+            kind: Field(format!("WarpKind::{}", kind)),
+            pos: Field(format!("vec2({}, {})", x * 256, y * 256)),
+            size: Field(format!("vec2({}, {})", w * 256, h * 256)),
+            room: target_room,
+            entry: entry_pos(entry),
+        });
+    }
+    out
+}
+
+// Rectangles of type `camera` keep the view inside them while the player is
+// in them. `scroll_up`/`scroll_down` set to false stop the view moving that way.
+fn embed_camera_zones(level: &serde_json::Value) -> Vec<CameraZone> {
+    objects(level).into_iter().filter(|c| c["type"] == "camera").map(|i| {
+        let (x, y, w, h) = object_rect(i);
+        CameraZone {
+            pos: Field(format!("vec2({}, {})", x, y)),
+            size: Field(format!("vec2({}, {})", w, h)),
+            scroll_up: property(i, "scroll_up").and_then(|c| c.as_bool()).unwrap_or(true),
+            scroll_down: property(i, "scroll_down").and_then(|c| c.as_bool()).unwrap_or(true),
+        }
+    }).collect()
+}
+
+// The polyline of type `scroll_path` that an autoscrolling room's view
+// centre travels along, as a list of points in pixels.
+fn embed_scroll_path(level: &serde_json::Value) -> Vec<String> {
+    let line = match objects(level).into_iter().find(|c| c["type"] == "scroll_path") {
+        Some(c) => c,
+        None => return vec![],
+    };
+    let (x, y) = (line["x"].as_f64().unwrap(), line["y"].as_f64().unwrap());
+    line["polyline"].as_array().unwrap().iter().map(|c| {
+        format!("vec2({}, {})", (x + c["x"].as_f64().unwrap()) as i32, (y + c["y"].as_f64().unwrap()) as i32)
+    }).collect()
+}
+
+// Cuts every tileset in assets/fg into 16x16 tiles and stores each distinct
+// tile once in a pool at TILE_DATA. Each tileset has its own palette, and
+// TILESETS maps a tileset's own tile numbers onto the pool through TILE_INDEX.
+fn embed_tilesets(mut f: impl io::Write, names: &[String], data: &mut Vec<u8>, pal: &mut Vec<u32>) {
+    let offset = data.len();
+    let mut pool: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut index = vec![];
+    let mut sets = vec![];
+    for name in names {
+        let image = image::open(format!("assets/fg/{}.png", name)).unwrap().to_rgba8();
+        let tiles = index.len();
+        let pal_offset = pal.len();
+        // Index 0 is kept for transparency
+        let mut palette = HashMap::new();
+        pal.push(0);
+        for ty in 0..image.height()/16 {
+            for tx in 0..image.width()/16 {
+                let tile = image.view(tx*16, ty*16, 16, 16).pixels().map(|(_,_,c)| {
+                    if c.0[3] == 0 { return 0; }
+                    let c = u32::from_le_bytes(c.0);
+                    let len = palette.len() + 1;
+                    *palette.entry(c).or_insert_with(|| {
+                        pal.push(c);
+                        len
+                    })
+                }).collect::<Vec<_>>();
+                if palette.len() > 255 {
+                    panic!("{}.png uses more than 255 colours", name);
+                }
+                let tile = tile.iter().map(|&c| c as u8).collect::<Vec<_>>();
+                let next = pool.len();
+                let id = *pool.entry(tile.clone()).or_insert_with(|| {
+                    data.extend(&tile);
+                    next
+                });
+                index.push(id);
+            }
+        }
+        sets.push(Tileset { tiles, len: index.len() - tiles, pal: pal_offset });
+    }
+    writeln!(f, "pub const TILE_DATA: usize = {:#X};", offset).unwrap();
+    writeln!(f, "pub static TILE_INDEX: [u16; {}] = {:?};", index.len(), index).unwrap();
+    Tileset::write_start(&mut f, "TILESETS", sets.len());
+    for i in sets.iter() {
+        i.write_entry(&mut f);
+    }
+    Tileset::write_end(&mut f);
+}
+
+// Per-tile data from Tiled for tileset `t`, read from the tileset embedded
+// in each map that uses the tileset image.
+fn tileset_tiles<'a>(name: &str, t: usize, maps: &'a [serde_json::Value], map_tilesets: &'a [usize]) -> Vec<&'a serde_json::Value> {
+    let image = format!("{}.png", name);
+    maps.iter().zip(map_tilesets).filter(|c| *c.1 == t)
+        .flat_map(|c| c.0["tilesets"].as_array().unwrap())
+        .filter(|c| c["image"].as_str().map_or(false, |c| c.ends_with(&image)))
+        .flat_map(|c| c["tiles"].as_array().into_iter().flatten())
+        .collect()
+}
+
+// Blocks are stored as bytes, so only the first 0x100 tiles of a tileset can
+// be placed in a map; animations and tops on any other tile are a mistake.
+const BLOCK_TILES: u64 = 0x100;
+
+fn block_tile(tile: &serde_json::Value, name: &str, what: &str) -> u16 {
+    let id = tile["id"].as_u64().unwrap();
+    if id >= BLOCK_TILES {
+        panic!("{}.png tile {:#X} has {}, but only tiles below {:#X} can be blocks", name, id, what, BLOCK_TILES);
+    }
+    id as u16
+}
+
+// Tile animations, with durations converted to 60fps frames and stored as
+// the frame each step ends on.
+fn embed_tile_anims(mut f: impl io::Write, names: &[String], maps: &[serde_json::Value], map_tilesets: &[usize]) {
+    let mut anims = vec![];
+    let mut frames = vec![];
+    let mut anim_list = vec![];
+    for (t, name) in names.iter().enumerate() {
+        anim_list.push(anims.len());
+        let mut seen = vec![];
+        for tile in tileset_tiles(name, t, maps, map_tilesets) {
+            let steps = match tile["animation"].as_array() {
+                Some(c) if !c.is_empty() => c,
+                _ => continue,
+            };
+            let id = block_tile(tile, name, "an animation");
+            if seen.contains(&id) { continue; }
+            seen.push(id);
+            let first = frames.len();
+            let mut end = 0;
+            for step in steps {
+                end += (step["duration"].as_u64().unwrap() * 60 / 1000).max(1) as u16;
+                frames.push(AnimFrame { tile: step["tileid"].as_u64().unwrap() as u16, end });
+            }
+            anims.push(TileAnim { tile: id, frames: first, len: frames.len() - first, period: end });
+        }
+    }
+    anim_list.push(anims.len());
+    TileAnim::write_start(&mut f, "TILE_ANIMS", anims.len());
+    for i in anims.iter() {
+        i.write_entry(&mut f);
+    }
+    TileAnim::write_end(&mut f);
+    AnimFrame::write_start(&mut f, "ANIM_FRAMES", frames.len());
+    for i in frames.iter() {
+        i.write_entry(&mut f);
+    }
+    AnimFrame::write_end(&mut f);
+    writeln!(f, "pub static TILE_ANIM_OFFSET: [usize; {}] = {:?};", anim_list.len(), anim_list).unwrap();
+}
+
+// Tiles with an int property `top` draw that tile over the block above them,
+// for edges like grass lips that overhang into the empty space.
+fn embed_tile_tops(mut f: impl io::Write, names: &[String], maps: &[serde_json::Value], map_tilesets: &[usize]) {
+    let mut tops = vec![];
+    let mut top_list = vec![];
+    for (t, name) in names.iter().enumerate() {
+        top_list.push(tops.len());
+        let first = tops.len();
+        for tile in tileset_tiles(name, t, maps, map_tilesets) {
+            if let Some(top) = property(tile, "top").and_then(|c| c.as_u64()) {
+                let id = block_tile(tile, name, "a top");
+                if tops[first..].iter().any(|c: &TileTop| c.tile == id) { continue; }
+                tops.push(TileTop { tile: id, top: top as u16 });
+            }
+        }
+    }
+    top_list.push(tops.len());
+    TileTop::write_start(&mut f, "TILE_TOPS", tops.len());
+    for i in tops.iter() {
+        i.write_entry(&mut f);
+    }
+    TileTop::write_end(&mut f);
+    writeln!(f, "pub static TILE_TOP_OFFSET: [usize; {}] = {:?};", top_list.len(), top_list).unwrap();
+}
+
 fn embed_fg(image: &image::RgbaImage, size: u32, data: &mut Vec<u8>, pal: &mut Vec<u32>) -> DataDef {
     let mut palette = HashMap::new();
     let offset = data.len();
@@ -188,7 +890,55 @@ fn embed_fg(image: &image::RgbaImage, size: u32, data: &mut Vec<u8>, pal: &mut V
 }
 
 
-fn embed_bg(image: &image::RgbaImage, data: &mut Vec<u8>, pal: &mut Vec<u32>) -> DataDef {
+// A level's background, drawn back to front. Either a single image name or a
+// list of layers: {"image", "scroll": [x, y], "speed": [x, y], "repeat":
+// "x"/"y"/"both"/"none", "anchor": "top"/"bottom"/y}. Scroll factors are
+// relative to the camera and speeds are in pixels per frame. In vertical
+// rooms a y scroll factor of 0 means the default vertical parallax.
+fn bg_layers(value: &serde_json::Value, images: &mut Vec<String>) -> Vec<BgLayer> {
+    let pair = |c: &serde_json::Value, default: [f64; 2]| {
+        let x = c[0].as_f64().unwrap_or(default[0]);
+        let y = c[1].as_f64().unwrap_or(default[1]);
+        Field(format!("vec2({}, {})", (x * 256.0) as i32, (y * 256.0) as i32))
+    };
+    let list = match value {
+        serde_json::Value::String(c) => vec![serde_json::json!({ "image": c })],
+        c => c.as_array().unwrap().clone(),
+    };
+    list.iter().map(|layer| {
+        let name = layer["image"].as_str().unwrap();
+        let (_, height) = image::image_dimensions(format!("assets/bg/{}.png", name)).unwrap();
+        let repeat = match layer["repeat"].as_str().unwrap_or("x") {
+            "none" => "None",
+            "y" => "Y",
+            "both" => "Both",
+            _ => "X",
+        };
+        let anchor = match &layer["anchor"] {
+            serde_json::Value::String(c) if c == "bottom" => 180 - height as i32,
+            c => c.as_i64().unwrap_or(0) as i32,
+        };
+        BgLayer {
+            image: intern(images, name),
+            scroll: pair(&layer["scroll"], [0.25, 0.0]),
+            speed: pair(&layer["speed"], [0.0, 0.0]),
+            repeat: Field(format!("Repeat::{}", repeat)),
+            anchor,
+        }
+    }).collect()
+}
+
+// The colour behind every background layer, as "#rrggbb". Black if not given.
+fn sky_color(value: &serde_json::Value) -> u32 {
+    let hex = match value.as_str() {
+        Some(c) => c.strip_prefix('#').expect("sky colour must look like #rrggbb"),
+        None => return 0xFF000000,
+    };
+    let rgb = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6).expect("sky colour must look like #rrggbb");
+    u32::from_le_bytes([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 0xFF])
+}
+
+fn embed_bg(image: &image::RgbaImage, data: &mut Vec<u8>, pal: &mut Vec<u32>) -> BgImage {
     let mut palette = HashMap::new();
     let offset = data.len();
     let pal_offset = pal.len();
@@ -201,8 +951,10 @@ fn embed_bg(image: &image::RgbaImage, data: &mut Vec<u8>, pal: &mut Vec<u32>) ->
         });
         *id as u8
     }));
-    DataDef {
+    BgImage {
         offset,
         pal: pal_offset,
+        width: image.width() as u16,
+        height: image.height() as u16,
     }
 }
@@ -1,20 +1,52 @@
-use crate::vec2::Vec2;
-use crate::data;
+use crate::vec2::{vec2, Vec2};
+use crate::data::{self, Repeat};
 use crate::Framebuffer;
 pub struct Background {
+    pub level: usize,
+    pub timer: i32,
+    // Set for vertical rooms: layers without a vertical scroll factor of their
+    // own follow the camera at VERTICAL_SCROLL, wrapping around.
+    pub vertical: bool,
 }
 
+const VERTICAL_SCROLL: i32 = 64;
+
 impl Background {
+    pub fn process(&mut self) {
+        self.timer += 1;
+    }
     pub fn render(&self, offset: Vec2<i32>, fb: &mut Framebuffer) {
-        let mut offset = offset / 4;
-        offset.y = 0;
-        for (pos,i) in fb.pixels() {
-            //if pos.y == 0 { continue; }
-            let pos = pos + offset;
-            let b = ((pos.x % 480) + pos.y * 480) as usize;
-            let px = data::BG.data()[b] as usize;
-            let color = data::BG.pal()[px];
-            *i = color;
+        for (_,i) in fb.pixels() {
+            *i = data::LEVELS[self.level].sky;
+        }
+        let (l,r) = (data::BG_LAYER_OFFSET[self.level], data::BG_LAYER_OFFSET[self.level+1]);
+        for layer in data::BG_LAYERS[l..r].iter() {
+            let bg = &data::BACKGROUNDS[layer.image];
+            let size = vec2(bg.width as i32, bg.height as i32);
+            let (wrap_x, mut wrap_y) = match layer.repeat {
+                Repeat::None => (false, false),
+                Repeat::X => (true, false),
+                Repeat::Y => (false, true),
+                Repeat::Both => (true, true),
+            };
+            let mut scroll = layer.scroll;
+            if self.vertical && scroll.y == 0 {
+                scroll.y = VERTICAL_SCROLL;
+                wrap_y = true;
+            }
+            let shift = (offset * scroll + layer.speed * self.timer) / 256 - vec2(0, layer.anchor);
+            for (pos,i) in fb.pixels() {
+                let mut pos = pos + shift;
+                if wrap_x { pos.x = pos.x.rem_euclid(size.x); }
+                if wrap_y { pos.y = pos.y.rem_euclid(size.y); }
+                if pos.x < 0 || pos.y < 0 || pos.x >= size.x || pos.y >= size.y { continue; }
+                let px = bg.data()[(pos.x + pos.y * size.x) as usize] as usize;
+                let color = bg.pal()[px];
+                // Fully transparent pixels show the layers behind
+                if color >> 24 != 0 {
+                    *i = color;
+                }
+            }
         }
     }
 }
@@ -0,0 +1,152 @@
+use crate::vec2::{Vec2, vec2};
+use crate::data::{self, ScrollMode};
+use crate::entity::EntityData;
+use crate::foreground::Foreground;
+use crate::framebuffer::Framebuffer;
+use crate::controller::Buttons;
+
+pub struct Camera {
+    pub pos: Vec2<i32>,
+    pub mode: ScrollMode,
+    // Autoscroll progress: index into SCROLL_PATH and subpixels along that segment
+    segment: usize,
+    track: i32,
+    speed: i32,
+    zone: Option<usize>,
+    blend: i32,
+    look: Vec2<i32>,
+    hold_timer: i32,
+    shake_timer: i32,
+    shake_strength: i32,
+}
+
+impl Camera {
+    // Frames taken to pan over when moving between zones.
+    const BLEND_TIME: i32 = 32;
+    // Frames Up or Down must be held while standing still before the view pans.
+    const HOLD_TIME: i32 = 30;
+    const LOOK_RANGE: i32 = 56;
+
+    pub fn init(&mut self, room: usize, target: &EntityData, fg: &Foreground) {
+        self.zone = Self::zone_at(room, target);
+        self.blend = 0;
+        self.look = vec2(0, 0);
+        self.hold_timer = 0;
+        self.shake_timer = 0;
+        self.mode = data::MAPS[room].scroll;
+        self.speed = data::MAPS[room].scroll_speed;
+        self.segment = data::SCROLL_PATH_OFFSET[room];
+        self.track = 0;
+        if self.mode == ScrollMode::Auto && self.segment == data::SCROLL_PATH_OFFSET[room+1] {
+            self.mode = ScrollMode::Free;
+        }
+        self.pos = match self.mode {
+            ScrollMode::Auto => data::SCROLL_PATH[self.segment] - Framebuffer::size()/2,
+            _ => target.visual_pos() - Framebuffer::size()/2,
+        };
+        self.pos = self.clamp(self.pos, fg);
+    }
+    // Moves the view centre along the scroll path, stopping at its last point.
+    fn autoscroll(&mut self, room: usize) -> Vec2<i32> {
+        let last = data::SCROLL_PATH_OFFSET[room+1] - 1;
+        if self.segment < last {
+            self.track += self.speed;
+        }
+        loop {
+            if self.segment >= last {
+                return data::SCROLL_PATH[last];
+            }
+            let (from, to) = (data::SCROLL_PATH[self.segment], data::SCROLL_PATH[self.segment+1]);
+            let len = (to - from).map(|c| c.abs()).reduce(|x,y| x.max(y)) * 256;
+            if self.track < len {
+                return from + (to - from) * (self.track / 256) / (len / 256);
+            }
+            self.track -= len;
+            self.segment += 1;
+        }
+    }
+    // Shakes the view by up to `strength` pixels for `frames` frames.
+    pub fn shake(&mut self, strength: i32, frames: i32) {
+        self.shake_strength = self.shake_strength.max(strength);
+        self.shake_timer = self.shake_timer.max(frames);
+    }
+    // Position to render from, including shake.
+    pub fn view(&self) -> Vec2<i32> {
+        if self.shake_timer == 0 { return self.pos; }
+        let t = self.shake_timer;
+        let jitter = vec2((t * 7) % 3 - 1, (t * 5 + 1) % 3 - 1);
+        self.pos + jitter * self.shake_strength.min(t / 2 + 1)
+    }
+    fn zone_at(room: usize, target: &EntityData) -> Option<usize> {
+        let (l,r) = (data::CAMERA_ZONE_OFFSET[room], data::CAMERA_ZONE_OFFSET[room+1]);
+        data::CAMERA_ZONES[l..r].iter()
+            .position(|c| c.contains(target.visual_pos()))
+            .map(|c| c + l)
+    }
+    // Limits a camera position to the room and the current zone.
+    fn clamp(&self, pos: Vec2<i32>, fg: &Foreground) -> Vec2<i32> {
+        let mut min = vec2(0, 6);
+        let mut max = vec2(fg.width() as i32, fg.height() as i32) * 16 - Framebuffer::size() - vec2(0, 6);
+        if let Some(zone) = self.zone.map(|c| &data::CAMERA_ZONES[c]) {
+            min = min.zip(zone.pos, |a,b| a.max(b));
+            max = max.zip(zone.pos + zone.size - Framebuffer::size(), |a,b| a.min(b));
+            // Zones smaller than the screen keep it at their top left
+            max = max.zip(min, |a,b| a.max(b));
+        }
+        pos.zip(min, |a,b| a.max(b)).zip(max, |a,b| a.min(b))
+    }
+    // Offset from the target: ahead of its movement, or up/down when asked to look.
+    fn update_look(&mut self, target: &EntityData, b: Buttons) {
+        let idle = target.on_ground && target.vel.x == 0;
+        if idle && (b.up() || b.down()) {
+            self.hold_timer += 1;
+        } else {
+            self.hold_timer = 0;
+        }
+        let goal = vec2(
+            target.vel.x / 16,
+            if self.hold_timer >= Self::HOLD_TIME {
+                if b.up() { -Self::LOOK_RANGE } else { Self::LOOK_RANGE }
+            } else if self.mode == ScrollMode::Vertical {
+                target.vel.y / 16
+            } else {
+                0
+            },
+        );
+        self.look += (goal - self.look).map(|c| c.signum() * c.abs().min(2));
+    }
+    pub fn update(&mut self, room: usize, target: &EntityData, fg: &Foreground, b: Buttons) {
+        let zone = Self::zone_at(room, target);
+        if zone != self.zone {
+            self.zone = zone;
+            self.blend = Self::BLEND_TIME;
+        }
+
+        self.update_look(target, b);
+        if self.shake_timer > 0 {
+            self.shake_timer -= 1;
+            if self.shake_timer == 0 { self.shake_strength = 0; }
+        }
+
+        if self.mode == ScrollMode::Auto {
+            let centre = self.autoscroll(room);
+            self.pos = self.clamp(centre - Framebuffer::size()/2, fg);
+            return;
+        }
+
+        let pivot = target.visual_pos() + self.look - Framebuffer::size()/2;
+        let mut follow = self.pos + (pivot - self.pos).map(|c| c.signum() * (c.abs() - 4).max(0));
+        if let Some(zone) = self.zone.map(|c| &data::CAMERA_ZONES[c]) {
+            if !zone.scroll_up { follow.y = follow.y.max(self.pos.y); }
+            if !zone.scroll_down { follow.y = follow.y.min(self.pos.y); }
+        }
+        let goal = self.clamp(follow, fg);
+
+        if self.blend > 0 {
+            self.pos += (goal - self.pos) / self.blend;
+            self.blend -= 1;
+        } else {
+            self.pos = goal;
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::lz4;
 use crate::entity::EntityEntry;
+use crate::foreground::BlockItem;
 use crate::vec2::{vec2,Vec2};
 
 pub struct DataDef {
@@ -16,17 +17,212 @@ impl DataDef {
     }
 }
 
+// A tileset's tile numbers, mapped onto the shared tile pool by TILE_INDEX,
+// and its own palette.
+pub struct Tileset {
+    pub tiles: usize,
+    pub len: usize,
+    pub pal: usize,
+}
+
+impl Tileset {
+    // The 16x16 pixels of tile `id`, which is blank if not in the tileset.
+    pub fn tile(&self, id: usize) -> &'static [u8] {
+        let index = if id < self.len { TILE_INDEX[self.tiles + id] as usize } else { 0 };
+        unsafe { &DATA[TILE_DATA + index*256..TILE_DATA + index*256 + 256] }
+    }
+    pub fn pal(&self) -> &'static [u32] {
+        &PAL_DATA[self.pal..]
+    }
+}
+
+// An animated tile of a tileset. Its ANIM_FRAMES each show `tile` until the
+// animation has run for `end` frames, looping every `period` frames.
+pub struct TileAnim {
+    pub tile: u16,
+    pub frames: usize,
+    pub len: usize,
+    pub period: u16,
+}
+
+impl TileAnim {
+    pub fn frame(&self, tick: i32) -> u16 {
+        let t = (tick % self.period as i32) as u16;
+        let frames = &ANIM_FRAMES[self.frames..self.frames+self.len];
+        frames.iter().find(|c| t < c.end).map_or(self.tile, |c| c.tile)
+    }
+}
+
+pub struct AnimFrame {
+    pub tile: u16,
+    pub end: u16,
+}
+
+// Drawn over the block above any `tile` of a tileset.
+pub struct TileTop {
+    pub tile: u16,
+    pub top: u16,
+}
+
+pub struct BgImage {
+    pub offset: usize,
+    pub pal: usize,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl BgImage {
+    pub fn data(&self) -> &'static [u8] {
+        unsafe { &DATA[self.offset..] }
+    }
+    pub fn pal(&self) -> &'static [u32] {
+        &PAL_DATA[self.pal..]
+    }
+}
+
+pub enum Repeat {
+    None,
+    X,
+    Y,
+    Both,
+}
+
+// One parallax layer of a level background. `scroll` is the fraction of the
+// camera movement it follows and `speed` its drift per frame, both in 1/256.
+// `anchor` is the screen y of the image's top edge when the camera is at y=0.
+pub struct BgLayer {
+    pub image: usize,
+    pub scroll: Vec2<i32>,
+    pub speed: Vec2<i32>,
+    pub repeat: Repeat,
+    pub anchor: i32,
+}
+
 pub struct LevelDef {
     pub offset: usize,
-    pub width: u8,
-    pub height: u8,
-    pub start_pos: Vec2<i32>
+    pub back: Option<usize>,
+    pub front: Option<usize>,
+    pub width: u16,
+    pub height: u16,
+    pub start_pos: Vec2<i32>,
+    pub tileset: usize,
+    // Block beyond the left, right, top and bottom edges
+    pub outside: [u8; 4],
+    // Gem blocks and gem boxes in the room
+    pub gems: u16,
+    pub scroll: ScrollMode,
+    // Autoscroll speed in 1/256 pixels per frame.
+    pub scroll_speed: i32,
+}
+
+// How the camera moves through a room. `Auto` follows the room's
+// SCROLL_PATH points at a fixed speed, whatever the player does.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ScrollMode {
+    Free,
+    Vertical,
+    Auto,
 }
 
 impl LevelDef {
     pub fn data(&self) -> &'static [u8] {
         unsafe { &DATA[self.offset..] }
     }
+    pub fn back(&self) -> Option<&'static [u8]> {
+        self.back.map(|c| unsafe { &DATA[c..] })
+    }
+    pub fn front(&self) -> Option<&'static [u8]> {
+        self.front.map(|c| unsafe { &DATA[c..] })
+    }
+}
+
+pub enum WarpKind {
+    Door,
+    Pipe,
+    Exit,
+}
+
+// A door, pipe or exit leading to `entry` in another room.
+pub struct Warp {
+    pub kind: WarpKind,
+    pub pos: Vec2<i32>,
+    pub size: Vec2<i32>,
+    pub room: usize,
+    pub entry: Vec2<i32>,
+}
+
+impl Warp {
+    pub fn contains(&self, pos: Vec2<i32>) -> bool {
+        let rel = pos - self.pos;
+        rel.x >= 0 && rel.y >= 0 && rel.x < self.size.x && rel.y < self.size.y
+    }
+}
+
+// Keeps the camera inside `pos..pos+size` (in pixels) while the player is in it.
+pub struct CameraZone {
+    pub pos: Vec2<i32>,
+    pub size: Vec2<i32>,
+    pub scroll_up: bool,
+    pub scroll_down: bool,
+}
+
+impl CameraZone {
+    pub fn contains(&self, pos: Vec2<i32>) -> bool {
+        let rel = pos - self.pos;
+        rel.x >= 0 && rel.y >= 0 && rel.x < self.size.x && rel.y < self.size.y
+    }
+}
+
+// A level from the manifest, made of consecutive rooms.
+pub struct LevelInfo {
+    pub name: &'static [u8],
+    pub world: u8,
+    pub number: u8,
+    pub first_room: usize,
+    pub rooms: usize,
+    pub music: u8,
+    // Shown wherever no background layer is drawn
+    pub sky: u32,
+}
+
+impl LevelInfo {
+    pub fn contains(&self, room: usize) -> bool {
+        room >= self.first_room && room < self.first_room + self.rooms
+    }
+    pub fn gems(&self) -> i32 {
+        MAPS[self.first_room..self.first_room+self.rooms].iter().map(|c| c.gems as i32).sum()
+    }
+}
+
+// A stop on the overworld, in screen pixels.
+pub struct Node {
+    pub pos: Vec2<i32>,
+    pub level: Option<usize>,
+}
+
+// An overworld path, walkable both ways once `unlock` is complete.
+pub struct Route {
+    pub from: usize,
+    pub to: usize,
+    pub unlock: Option<usize>,
+}
+
+impl Route {
+    pub fn other(&self, node: usize) -> Option<usize> {
+        if self.from == node {
+            Some(self.to)
+        } else if self.to == node {
+            Some(self.from)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct MenuEntry {
+    pub text: &'static [u8],
+    pub level: usize,
+    pub room: usize,
 }
 
 pub fn init() {
@@ -1,6 +1,7 @@
 use crate::vec2::{Vec2, vec2};
 use crate::Framebuffer;
 use crate::data::DataDef;
+use crate::foreground::{Foreground, Hit};
 
 mod player;
 
@@ -14,8 +15,8 @@ pub struct EntitySet {
 }
 
 pub struct EntityEntry {
-    pub x: u8,
-    pub y: u8,
+    pub x: u16,
+    pub y: u16,
     pub kind: u8
 }
 
@@ -31,7 +32,13 @@ pub struct EntityData {
     pub pos: Vec2<i32>,
     pub vel: Vec2<i32>,
     pub on_ground: bool,
-    pub sensors: [*mut u8; 6],
+    pub ground: u8,
+    pub ground_pos: Vec2<i32>,
+    pub inside: u8,
+    pub platform: bool,
+    // Movement from what the entity stands on, swept along with `vel.x`
+    pub carry: i32,
+    pub drop_timer: i32,
     pub sensor_pos: [Vec2<i32>; 6],
     pub flip: Vec2<bool>,
     pub next_pos: Vec2<i32>,
@@ -48,7 +55,9 @@ pub enum EntityKind {
     Tomato,
     Bee,
     Snail,
-    Platform
+    Platform,
+    // Lit once touched
+    Checkpoint(bool),
 }
 
 pub enum EntityState {
@@ -59,11 +68,19 @@ pub enum EntityState {
     Dead
 }
 
+const CHECKPOINT_FRAME: i32 = 0x10;
+
 impl Entity {
     pub fn init(&mut self, id: u8) {
         self.kind.init_kind(id);
         self.kind.init(&mut self.data);
     }
+    pub fn light(&mut self) {
+        if let EntityKind::Checkpoint(lit) = &mut self.kind {
+            *lit = true;
+            self.data.sprites[0].frame = CHECKPOINT_FRAME + 1;
+        }
+    }
 }
 
 impl EntityKind {
@@ -76,7 +93,7 @@ impl EntityKind {
             EntityKind::Player(p) => {
                 p.init();
                 data.vel = vec2(0,0);
-                data.radius = vec2(0x400, 0xE00);
+                data.radius = player::Player::STAND_RADIUS;
                 data.sprites[0].active = true;
                 data.sprites[0].source = crate::data::TOOTHPASTE;
                 data.sprites[0].offset = vec2(-16, -16);
@@ -86,6 +103,17 @@ impl EntityKind {
                 data.sprites[1].offset = vec2(-16, -16);
                 data.sprites[1].frame = 0;
             },
+            EntityKind::Platform => {
+                data.radius = vec2(0x1800, 0x400);
+            },
+            EntityKind::Checkpoint(lit) => {
+                *lit = false;
+                data.radius = vec2(0x600, 0xE00);
+                data.sprites[0].active = true;
+                data.sprites[0].source = crate::data::ENTITIES;
+                data.sprites[0].offset = vec2(-16, -18);
+                data.sprites[0].frame = CHECKPOINT_FRAME;
+            },
             EntityKind::Tomato => {
                 data.radius = vec2(0x400, 0x400);
                 data.flip.x = true;
@@ -98,10 +126,10 @@ impl EntityKind {
         }
     }
     pub fn process(&mut self, data: &mut EntityData) {
-        let camera = crate::state().as_level().camera;
+        let camera = crate::state().as_level().camera.pos;
         let x_delta = data.pos.x - camera.x * 0x100;
         if x_delta < -0x2000 || x_delta > 0x16000 { return; }
-        let fg = &crate::state().as_level().fg;
+        let fg = &mut crate::state().as_level().fg;
         let level_h = fg.height() as i32 * 16 * 256;
         if data.pos.y > level_h { *self = EntityKind::None; return; }
         match data.state {
@@ -136,9 +164,14 @@ impl EntityKind {
                         3 => 1,
                         _ => panic!()
                     };
-                    data.physics();
+                    data.physics(fg, &crate::state().as_level().entities.inner);
                     if data.vel.x == 0 { data.flip.x = !data.flip.x; }
                 }
+                EntityKind::Platform => {
+                    data.anim_timer += 1;
+                    data.vel.x = if data.anim_timer / 128 & 1 == 0 { 0x80 } else { -0x80 };
+                    data.pos += data.vel;
+                }
                 _ => {}
             }
             _ => {}
@@ -173,8 +206,10 @@ impl EntitySet {
     }
     pub fn render(&self, camera: Vec2<i32>, fb: &mut Framebuffer) {
         for i in self.inner.iter() {
-            if !matches!(i.kind, EntityKind::None) {
-                i.data.render(camera, fb);
+            match i.kind {
+                EntityKind::None => {},
+                EntityKind::Platform => i.data.render_tiles(&[0x38, 0x39, 0x3A], camera, fb),
+                _ => i.data.render(camera, fb),
             }
         }
     }
@@ -229,46 +264,427 @@ impl EntityData {
             }*/
         }
     }
-    pub fn physics(&mut self) {
-        let fg = &mut crate::state().as_level().fg;
+    // Largest distance moved between two sensor checks, and the depth into a
+    // semisolid that still lands on it, so nothing can skip past the top of a
+    // block. Inclusive, since grounded sensors start exactly at the top.
+    const MAX_STEP: i32 = 0x400;
+    // Draws a row of foreground blocks centered on the entity.
+    pub fn render_tiles(&self, tiles: &[u8], camera: Vec2<i32>, fb: &mut Framebuffer) {
+        let fg = &crate::state().as_level().fg;
+        let pal = fg.tileset().pal();
+        let origin = self.visual_pos() - camera - vec2(tiles.len() as i32 * 8, self.radius.y / 256);
+        for (t,block) in tiles.iter().enumerate() {
+            let gfx = fg.tile(*block as usize);
+            for i in 0..256 {
+                let px = gfx[i];
+                if px != 0 {
+                    let pos = origin + vec2(t as i32 * 16 + (i & 0xF) as i32, (i >> 4) as i32);
+                    fb.pixel(pos).map(|c| *c = pal[px as usize]);
+                }
+            }
+        }
+    }
+    // Moves by `vel`, stopping at blocks and landing on blocks and platforms
+    // from `entities`.
+    pub fn physics(&mut self, fg: &mut Foreground, entities: &[Entity]) {
         let mut next_pos = self.pos;
 
         let last_on_ground = self.on_ground;
         self.on_ground = false;
+        self.platform = false;
+        self.ground = 0;
+        if self.drop_timer > 0 { self.drop_timer -= 1; }
         for axis in 0..2 {
-            next_pos[axis] += self.vel[axis];
-            let direction = self.vel[axis] >= 0;
-            let mut sensor_pos = if direction {
-                self.radius[axis]
-            } else {
-                -self.radius[axis]
-            };
-            if axis == 1 && last_on_ground { sensor_pos += 256; }
-            for i in -1..=1 {
-                let p = axis * 3 + (i+1) as usize;
-                let mut offset = self.radius * i;
-                offset[axis] = sensor_pos;
-                self.sensor_pos[p] = ((next_pos + offset) & !0xFFF) + vec2(0x800, 0x800);
-                let sensor = (next_pos + offset) / 16 / 256;
-                if !fg.in_bounds(sensor) { continue; }
-                let block = unsafe { &mut *fg.mut_ptr_at(sensor) };
-                self.sensors[p] = block;
-                let coll = crate::foreground::collision(*block);
-                if coll.is_semisolid() {
-                    if direction && axis == 1 && (next_pos.y + sensor_pos) & 0xFFF < 0x400 {
-                        self.on_ground = true;
-                        next_pos.y = ((next_pos.y + sensor_pos) & !0xFFF) - self.radius.y - 0x100;
-                    }
-                } else if coll.is_solid() {
-                    if direction && axis == 1 {
-                        self.on_ground = true;
-                        next_pos.y = ((next_pos.y + sensor_pos) & !0xFFF) - self.radius.y - 0x100;
+            let start = next_pos[axis];
+            let motion = if axis == 0 { self.vel.x + self.carry } else { self.vel.y };
+            let steps = ((motion.abs() + Self::MAX_STEP - 1) / Self::MAX_STEP).max(1);
+            for step in 1..=steps {
+                let prev = next_pos[axis];
+                next_pos[axis] = start + motion * step / steps;
+                if self.sense(fg, entities, axis, motion >= 0, &mut next_pos, prev, last_on_ground) { break; }
+            }
+        }
+        self.pos = next_pos;
+        if !self.platform {
+            self.carry = if self.on_ground { crate::foreground::terrain(self.ground).conveyor() } else { 0 };
+        }
+        if self.on_ground { fg.hit(self.ground_pos, Hit::Stand); }
+        self.inside = fg.get(self.pos >> 12);
+    }
+    // Checks the three sensors on the leading edge of `axis`, resolving any
+    // collision in `next_pos`. Returns true if the movement was stopped.
+    fn sense(&mut self, fg: &mut Foreground, entities: &[Entity], axis: usize, direction: bool, next_pos: &mut Vec2<i32>, prev: i32, last_on_ground: bool) -> bool {
+        let mut sensor_pos = if direction {
+            self.radius[axis]
+        } else {
+            -self.radius[axis]
+        };
+        if axis == 1 && last_on_ground { sensor_pos += 256; }
+        let mut stopped = false;
+        let mut bumped = false;
+        for i in -1..=1 {
+            let p = axis * 3 + (i+1) as usize;
+            let mut offset = self.radius * i;
+            offset[axis] = sensor_pos;
+            self.sensor_pos[p] = ((*next_pos + offset) & !0xFFF) + vec2(0x800, 0x800);
+            let sensor = (*next_pos + offset) >> 12;
+            let block = fg.get(sensor);
+            let coll = crate::foreground::collision(block);
+            if coll.is_semisolid() {
+                if direction && axis == 1 && self.drop_timer == 0 && (next_pos.y + sensor_pos) & 0xFFF <= Self::MAX_STEP {
+                    self.land(block, sensor, i);
+                    next_pos.y = ((next_pos.y + sensor_pos) & !0xFFF) - self.radius.y - 0x100;
+                    stopped = true;
+                }
+            } else if coll.is_solid() {
+                if direction && axis == 1 {
+                    self.land(block, sensor, i);
+                    next_pos.y = ((next_pos.y + sensor_pos) & !0xFFF) - self.radius.y - 0x100;
+                } else {
+                    if axis == 1 && !bumped { bumped = fg.hit(sensor, Hit::Below); }
+                    // Against the block's edge, but never back past where this step started
+                    let edge = (next_pos[axis] + sensor_pos) & !0xFFF;
+                    next_pos[axis] = if direction {
+                        (edge - self.radius[axis] - 1).max(prev)
                     } else {
-                        next_pos[axis] = self.pos[axis]; self.vel[axis] = 0;
-                    }
+                        (edge + 0x1000 + self.radius[axis]).min(prev)
+                    };
+                    self.vel[axis] = 0;
                 }
+                stopped = true;
             }
         }
-        self.pos = next_pos;
+        if direction && axis == 1 && self.drop_timer == 0 && !stopped {
+            stopped = self.ride(entities, next_pos, sensor_pos);
+        }
+        stopped
+    }
+    // Lands on a platform entity; platforms are one-way, like semisolid blocks.
+    // Their movement is carried through the next frame's sweep, so riders
+    // still stop at walls.
+    fn ride(&mut self, entities: &[Entity], next_pos: &mut Vec2<i32>, sensor_pos: i32) -> bool {
+        for e in entities.iter() {
+            if !matches!(e.kind, EntityKind::Platform) { continue; }
+            if &e.data as *const _ == self as *const _ { continue; }
+            let top = e.data.pos.y - e.data.radius.y;
+            let feet = next_pos.y + sensor_pos - top;
+            let dist = (next_pos.x - e.data.pos.x).abs();
+            if dist < e.data.radius.x + self.radius.x && feet >= 0 && feet <= Self::MAX_STEP {
+                self.on_ground = true;
+                self.platform = true;
+                self.ground_pos = vec2(-1, -1);
+                next_pos.y = top - self.radius.y - 0x100;
+                self.carry = e.data.vel.x;
+                return true;
+            }
+        }
+        false
+    }
+    pub fn on_semisolid(&self) -> bool {
+        self.platform || crate::foreground::collision(self.ground).is_semisolid()
+    }
+    // Records the block being stood on, preferring the one under the center.
+    fn land(&mut self, block: u8, pos: Vec2<i32>, sensor: i32) {
+        if sensor == 0 || !self.on_ground {
+            self.ground = block;
+            self.ground_pos = pos;
+        }
+        self.on_ground = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::foreground::{Foreground, Terrain, terrain};
+    use std::boxed::Box;
+
+    const SOLID: u8 = 0x01;
+    const SEMISOLID: u8 = 0x34;
+    const SPEEDS: [i32; 8] = [0x400, 0x800, 0xC00, 0x1000, 0x1800, 0x2000, 0x3000, 0x4000];
+    const RADII: [Vec2<i32>; 3] = [vec2(0x100, 0x100), vec2(0x300, 0x300), player::Player::STAND_RADIUS];
+
+    // Builds a map from rows of `#` (solid), `=` (semisolid), `i` (ice),
+    // `<` and `>` (conveyors), `m` (mud), `~` (water) and `.` (empty).
+    fn map(rows: &[&str]) -> Box<Foreground> {
+        let mut fg: Box<Foreground> = unsafe {
+            let layout = std::alloc::Layout::new::<Foreground>();
+            Box::from_raw(std::alloc::alloc_zeroed(layout) as *mut Foreground)
+        };
+        fg.init(rows[0].len(), rows.len());
+        for (y,row) in rows.iter().enumerate() {
+            for (x,c) in row.bytes().enumerate() {
+                let block = match c {
+                    b'#' => SOLID,
+                    b'=' => SEMISOLID,
+                    b'i' => 0xB0,
+                    b'<' => 0xB4,
+                    b'>' => 0xB5,
+                    b'm' => 0xB6,
+                    b'~' => 0xBA,
+                    _ => 0,
+                };
+                fg.set(vec2(x as i32, y as i32), block);
+            }
+        }
+        fg
+    }
+    fn entity(pos: Vec2<i32>, radius: Vec2<i32>, vel: Vec2<i32>) -> EntityData {
+        let mut e: EntityData = unsafe { core::mem::zeroed() };
+        e.pos = pos;
+        e.radius = radius;
+        e.vel = vel;
+        e
+    }
+    // An entity of the player's size standing on the floor at row `floor`.
+    fn standing(x: i32, floor: i32, vel: Vec2<i32>) -> EntityData {
+        let radius = player::Player::STAND_RADIUS;
+        let mut e = entity(vec2(x, floor * 0x1000 - radius.y - 0x100), radius, vel);
+        e.on_ground = true;
+        e
+    }
+    // Moves like the player, with the movement constants for the terrain:
+    // walking towards `dir`, braking without one, and falling.
+    fn walk(e: &mut EntityData, fg: &mut Foreground, frames: usize, dir: i32) {
+        for _ in 0..frames {
+            let ground = if e.on_ground { terrain(e.ground) } else { Terrain::Normal };
+            let m = player::Movement::new(ground, terrain(e.inside) == Terrain::Water);
+            if dir != 0 {
+                e.vel.x = (e.vel.x + dir * m.accel).clamp(-m.walk_speed, m.walk_speed);
+            } else {
+                e.vel.x -= e.vel.x.signum() * m.friction.min(e.vel.x.abs());
+            }
+            e.vel.y = if e.on_ground { 0 } else { (e.vel.y + m.gravity).min(m.fall_speed) };
+            e.physics(fg, &[]);
+        }
+    }
+    // Runs `frames` frames at a constant velocity, checking `ok` after each.
+    fn run(e: &mut EntityData, fg: &mut Foreground, frames: usize, ok: impl Fn(&EntityData) -> bool) {
+        for frame in 0..frames {
+            e.physics(fg, &[]);
+            assert!(ok(e), "frame {}: pos {:?} vel {:?} radius {:?}", frame, e.pos, e.vel, e.radius);
+        }
+    }
+
+    #[test]
+    fn lands_on_thin_floor() {
+        for &speed in SPEEDS.iter() {
+            for &radius in RADII.iter() {
+                let mut fg = map(&[
+                    "......",
+                    "......",
+                    "......",
+                    "......",
+                    "######",
+                    "......",
+                    "......",
+                    "......",
+                ]);
+                let mut e = entity(vec2(0x2800, 0x1000), radius, vec2(0, speed));
+                run(&mut e, &mut fg, 16, |e| e.pos.y + e.radius.y <= 0x4000);
+                assert!(e.on_ground);
+                assert_eq!(e.pos.y, 0x4000 - radius.y - 0x100);
+            }
+        }
+    }
+
+    #[test]
+    fn stops_at_thin_ceiling() {
+        for &speed in SPEEDS.iter() {
+            for &radius in RADII.iter() {
+                let mut fg = map(&[
+                    "......",
+                    "......",
+                    "......",
+                    "######",
+                    "......",
+                    "......",
+                    "......",
+                    "......",
+                ]);
+                let mut e = entity(vec2(0x2800, 0x7000 - radius.y), radius, vec2(0, -speed));
+                run(&mut e, &mut fg, 16, |e| e.pos.y - e.radius.y >= 0x4000);
+                assert_eq!(e.vel.y, 0);
+                assert_eq!(e.pos.y - e.radius.y, 0x4000);
+            }
+        }
+    }
+
+    #[test]
+    fn stops_at_thin_walls() {
+        for &speed in SPEEDS.iter() {
+            for &radius in RADII.iter() {
+                for &dir in [-1, 1].iter() {
+                    let mut fg = map(&[
+                        "...#................#...",
+                        "...#................#...",
+                        "...#................#...",
+                        "...#................#...",
+                    ]);
+                    let mut e = entity(vec2(0xC000, 0x2000), radius, vec2(speed * dir, 0));
+                    run(&mut e, &mut fg, 32, |e| {
+                        e.pos.x - e.radius.x >= 0x4000 && e.pos.x + e.radius.x < 0x14000
+                    });
+                    assert_eq!(e.vel.x, 0);
+                    let edge = if dir > 0 { e.pos.x + e.radius.x + 1 } else { e.pos.x - e.radius.x };
+                    assert_eq!(edge, if dir > 0 { 0x14000 } else { 0x4000 });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lands_on_semisolid() {
+        for &speed in SPEEDS.iter() {
+            for &radius in RADII.iter() {
+                let mut fg = map(&[
+                    "......",
+                    "......",
+                    "......",
+                    "......",
+                    "======",
+                    "......",
+                    "......",
+                    "......",
+                ]);
+                let mut e = entity(vec2(0x2800, 0x1000), radius, vec2(0, speed));
+                run(&mut e, &mut fg, 16, |e| e.pos.y + e.radius.y <= 0x4000);
+                assert!(e.on_ground);
+                assert_eq!(e.pos.y, 0x4000 - radius.y - 0x100);
+            }
+        }
+    }
+
+    #[test]
+    fn jumps_through_semisolid() {
+        for &speed in SPEEDS.iter() {
+            for &radius in RADII.iter() {
+                let mut fg = map(&[
+                    "......",
+                    "......",
+                    "......",
+                    "......",
+                    "======",
+                    "......",
+                    "......",
+                    "......",
+                ]);
+                let mut e = entity(vec2(0x2800, 0x8000 - radius.y - 0x100), radius, vec2(0, -speed));
+                let frames = (0x8000 / speed) as usize;
+                run(&mut e, &mut fg, frames, |e| e.vel.y == -speed);
+                assert!(e.pos.y + e.radius.y < 0x4000);
+            }
+        }
+    }
+
+    #[test]
+    fn slides_further_on_ice() {
+        let mut distance = [0; 2];
+        for (d, floor) in distance.iter_mut().zip([b'#', b'i'].iter()) {
+            let floor = std::string::String::from_utf8(std::vec![*floor; 32]).unwrap();
+            let mut fg = map(&[&".".repeat(32), &".".repeat(32), &floor]);
+            let mut e = standing(0x2000, 2, vec2(0x240, 0));
+            walk(&mut e, &mut fg, 200, 0);
+            assert_eq!(e.vel.x, 0);
+            *d = e.pos.x - 0x2000;
+        }
+        assert!(distance[1] > distance[0] * 2, "{:?}", distance);
+    }
+
+    #[test]
+    fn walks_slower_in_mud() {
+        let mut distance = [0; 2];
+        for (d, floor) in distance.iter_mut().zip([b'#', b'm'].iter()) {
+            let floor = std::string::String::from_utf8(std::vec![*floor; 32]).unwrap();
+            let mut fg = map(&[&".".repeat(32), &".".repeat(32), &floor]);
+            let mut e = standing(0x2000, 2, vec2(0, 0));
+            walk(&mut e, &mut fg, 60, 1);
+            assert!(e.on_ground);
+            *d = e.pos.x - 0x2000;
+        }
+        assert!(distance[1] * 5 < distance[0] * 3, "{:?}", distance);
+    }
+
+    #[test]
+    fn sinks_slower_in_water() {
+        for &(fluid, fall_speed) in [(".", 0x400), ("~", 0x180)].iter() {
+            let mut rows = std::vec![fluid; 12];
+            rows.push("#");
+            let mut fg = map(&rows);
+            let mut e = entity(vec2(0x800, 0x1000), player::Player::STAND_RADIUS, vec2(0, 0));
+            walk(&mut e, &mut fg, 30, 0);
+            assert!(!e.on_ground);
+            assert_eq!(e.vel.y, fall_speed);
+        }
+    }
+
+    #[test]
+    fn conveyor_carries_standing_entity() {
+        let mut fg = map(&["................", "................", ">>>>>>>>>>>>>>>>"]);
+        let mut e = standing(0x2000, 2, vec2(0, 0));
+        run(&mut e, &mut fg, 16, |e| e.on_ground && e.vel.x == 0);
+        // Carried from the second frame, once it knows what it stands on
+        assert_eq!(e.pos.x, 0x2000 + 15 * 0x100);
+    }
+
+    #[test]
+    fn keeps_velocity_against_conveyor() {
+        let mut fg = map(&["................", "................", "<<<<<<<<<<<<<<<<"]);
+        let mut e = standing(0x8000, 2, vec2(0x100, 0));
+        run(&mut e, &mut fg, 16, |e| e.on_ground && e.vel.x == 0x100);
+        assert_eq!(e.pos.x, 0x8100);
+    }
+
+    #[test]
+    fn conveyor_stops_at_walls() {
+        let mut fg = map(&["#...............", "#...............", "<<<<<<<<<<<<<<<<"]);
+        let mut e = standing(0x4000, 2, vec2(0, 0));
+        run(&mut e, &mut fg, 64, |e| e.on_ground && e.pos.x - e.radius.x >= 0x1000);
+        assert_eq!(e.pos.x - e.radius.x, 0x1000);
+    }
+
+    // A platform under an entity standing at `x` on row `floor`.
+    fn platform(x: i32, floor: i32, vel: i32) -> Entity {
+        let radius = vec2(0x1800, 0x400);
+        Entity { data: entity(vec2(x, floor * 0x1000 + radius.y), radius, vec2(vel, 0)), kind: EntityKind::Platform }
+    }
+    // Moves the platform, then the entity riding it, like the entity set does.
+    fn ride(e: &mut EntityData, fg: &mut Foreground, platform: &mut [Entity; 1], frames: usize, ok: impl Fn(&EntityData) -> bool) {
+        for frame in 0..frames {
+            platform[0].data.pos += platform[0].data.vel;
+            e.physics(fg, &platform[..]);
+            assert!(ok(e), "frame {}: pos {:?} vel {:?}", frame, e.pos, e.vel);
+        }
+    }
+
+    #[test]
+    fn rides_platform() {
+        let mut fg = map(&["................"; 8]);
+        let mut p = [platform(0x8000, 4, 0x80)];
+        let mut e = standing(0x8000, 4, vec2(0, 0));
+        ride(&mut e, &mut fg, &mut p, 32, |e| e.on_ground && e.platform);
+        // Carried from the second frame
+        assert_eq!(e.pos.x, 0x8000 + 31 * 0x80);
+        assert_eq!(e.pos.y, 0x4000 - e.radius.y - 0x100);
+    }
+
+    #[test]
+    fn platform_stops_riders_at_walls() {
+        let mut fg = map(&["#...............", "#...............", "#...............", "#..............."]);
+        let mut p = [platform(0x4000, 3, -0x80)];
+        let mut e = standing(0x3000, 3, vec2(0, 0));
+        ride(&mut e, &mut fg, &mut p, 64, |e| e.pos.x - e.radius.x >= 0x1000);
+        assert_eq!(e.pos.x - e.radius.x, 0x1000);
+    }
+
+    #[test]
+    fn lands_on_platform() {
+        for &speed in SPEEDS.iter() {
+            let mut fg = map(&["................"; 8]);
+            let mut p = [platform(0x8000, 4, 0)];
+            let mut e = entity(vec2(0x8000, 0x1000), player::Player::STAND_RADIUS, vec2(0, speed));
+            ride(&mut e, &mut fg, &mut p, 16, |e| e.pos.y + e.radius.y <= 0x4000);
+            assert!(e.on_ground && e.platform);
+        }
     }
 }
@@ -1,4 +1,7 @@
 use super::*;
+use crate::foreground::{self, Terrain, Hit};
+use crate::data::{WarpKind, ScrollMode};
+use crate::state::{Checkpoint, LevelState};
 
 #[derive(PartialEq)]
 pub enum PlayerPose {
@@ -8,6 +11,55 @@ pub enum PlayerPose {
     Hurt,
 }
 
+// Movement constants, adjusted by the terrain the player is standing in.
+pub(super) struct Movement {
+    pub(super) accel: i32,
+    pub(super) turn: i32,
+    pub(super) friction: i32,
+    pub(super) gravity: i32,
+    pub(super) gravity_held: i32,
+    pub(super) fall_speed: i32,
+    pub(super) walk_speed: i32,
+    pub(super) run_speed: i32,
+}
+
+impl Movement {
+    const NORMAL: Movement = Movement {
+        accel: 0x18,
+        turn: 0x50,
+        friction: 0x10,
+        gravity: 0x60,
+        gravity_held: 0x30,
+        fall_speed: 0x400,
+        walk_speed: 0x240,
+        run_speed: 0x300,
+    };
+    pub(super) fn new(ground: Terrain, water: bool) -> Self {
+        let mut m = Self::NORMAL;
+        match ground {
+            Terrain::Ice => {
+                m.accel = 0x08;
+                m.turn = 0x10;
+                m.friction = 0x04;
+            }
+            Terrain::Mud => {
+                m.friction = 0x20;
+                m.walk_speed = 0x100;
+                m.run_speed = 0x100;
+            }
+            _ => {}
+        }
+        if water {
+            m.gravity = 0x18;
+            m.gravity_held = 0x10;
+            m.fall_speed = 0x180;
+            m.walk_speed = m.walk_speed.min(0x180);
+            m.run_speed = m.run_speed.min(0x180);
+        }
+        m
+    }
+}
+
 pub struct Player {
     p_meter: i32,
     p_speed: bool,
@@ -17,6 +69,8 @@ pub struct Player {
     invuln_timer: i32,
 }
 impl Player {
+    pub const STAND_RADIUS: Vec2<i32> = vec2(0x400, 0xE00);
+    pub const CROUCH_RADIUS: Vec2<i32> = vec2(0x400, 0x700);
     pub fn init(&mut self) {
         self.p_meter = 0;
         self.p_speed = false;
@@ -25,8 +79,13 @@ impl Player {
     }
     pub fn hurt(&mut self, data: &mut EntityData) {
         let l = crate::state().as_level();
+        if l.death_timer > 0 { return; }
         l.health -= 1;
-        if l.health == 0 { l.init_flag = true; }
+        l.damage += 1;
+        if l.health == 0 {
+            self.die(data, true);
+            return;
+        }
         self.pose = PlayerPose::Hurt;
         data.vel = if data.flip.x {
             vec2(0x100, -0x500)
@@ -35,6 +94,46 @@ impl Player {
         };
         data.on_ground = false;
     }
+    // Starts the death sequence; `bounce` throws the player up before they fall.
+    pub fn die(&mut self, data: &mut EntityData, bounce: bool) {
+        let l = crate::state().as_level();
+        if l.death_timer > 0 { return; }
+        l.death_timer = LevelState::DEATH_TIME;
+        data.state = EntityState::Dead;
+        data.vel = if bounce { vec2(0, -0x500) } else { vec2(0, 0) };
+    }
+    pub fn radius(&self) -> Vec2<i32> {
+        match self.pose {
+            PlayerPose::Crouching | PlayerPose::Sliding => Self::CROUCH_RADIUS,
+            _ => Self::STAND_RADIUS
+        }
+    }
+    // Switches to the current pose's hitbox, keeping the feet in place.
+    fn resize(&self, data: &mut EntityData) {
+        let radius = self.radius();
+        let delta = data.radius.y - radius.y;
+        if delta == 0 { return; }
+        data.pos.y += delta;
+        data.radius = radius;
+        for spr in data.sprites[..2].iter_mut() {
+            spr.offset.y -= delta / 256;
+        }
+    }
+    // Whether there's room above a crouching player to stand up.
+    fn can_stand(data: &EntityData) -> bool {
+        let fg = &crate::state().as_level().fg;
+        let feet = data.pos.y + data.radius.y;
+        let top = feet - Self::STAND_RADIUS.y * 2;
+        for y in [top, feet - data.radius.y * 2].iter() {
+            for i in -1..=1 {
+                let sensor = vec2(data.pos.x + (data.radius.x - 1) * i, *y) >> 12;
+                if foreground::collision(fg.get(sensor)).is_solid() {
+                    return false;
+                }
+            }
+        }
+        true
+    }
     pub fn invincible(&self) -> bool {
         self.pose == PlayerPose::Sliding
     }
@@ -47,13 +146,19 @@ impl Player {
     pub fn run(&mut self, data: &mut EntityData) {
         let buttons = crate::state().buttons;
         if self.slide_timer > 0 { self.slide_timer -= 1; }
+        let ground = if data.on_ground { foreground::terrain(data.ground) } else { Terrain::Normal };
+        let water = foreground::terrain(data.inside) == Terrain::Water;
+        let m = Movement::new(ground, water);
         if !data.on_ground {
+            if water && buttons.a_edge() && self.pose != PlayerPose::Hurt {
+                data.vel.y = -0x300;
+            }
             if buttons.a() && self.pose != PlayerPose::Hurt {
-                data.vel.y += 0x30;
+                data.vel.y += m.gravity_held;
             } else {
-                data.vel.y += 0x60;
+                data.vel.y += m.gravity;
             }
-            data.vel.y = data.vel.y.min(1024);
+            data.vel.y = data.vel.y.min(m.fall_speed);
         } else {
             if self.pose == PlayerPose::Hurt {
                 self.invuln_timer = 60;
@@ -68,15 +173,25 @@ impl Player {
                     self.slide_timer = 0;
                     self.pose = PlayerPose::Crouching;
                 }
-            } else {
+            } else if self.pose == PlayerPose::Normal || self.pose == PlayerPose::Hurt || Self::can_stand(data) {
                 self.slide_timer = 0;
                 self.pose = PlayerPose::Normal;
+            } else {
+                // Keep sliding out from under a low ceiling instead of getting stuck
+                self.pose = PlayerPose::Sliding;
+                if data.vel.x.abs() < 0x100 {
+                    data.vel.x = if data.flip.x { -0x100 } else { 0x100 };
+                }
             }
-            if buttons.a_edge() {
+            if buttons.down() && buttons.a_edge() && data.on_semisolid() {
+                data.drop_timer = 8;
+                data.vel.y = 0x100;
+                data.on_ground = false;
+            } else if buttons.a_edge() {
                 let lift = 0x500 + data.vel.x.abs() * 5 / 16;
                 data.vel.y = -lift + 0x30;
                 data.on_ground = false;
-                if matches!(self.pose, PlayerPose::Sliding) {
+                if matches!(self.pose, PlayerPose::Sliding) && Self::can_stand(data) {
                     self.pose = PlayerPose::Normal;
                 }
             } else {
@@ -87,7 +202,8 @@ impl Player {
                 }
             }
         }
-        let max_speed = if self.p_meter == 0x70 { 0x300 } else { 0x240 };
+        self.resize(data);
+        let max_speed = if self.p_meter == 0x70 { m.run_speed } else { m.walk_speed };
         let mut neutral = true;
         let can_move = match self.pose {
             PlayerPose::Normal => true,
@@ -104,10 +220,10 @@ impl Player {
                 if button {
                     data.flip.x = i == 0;
                     if data.vel.x > 0 {
-                        if data.vel.x < max_speed { data.vel.x += 0x18; }
+                        if data.vel.x < max_speed { data.vel.x += m.accel; }
                         if data.vel.x >= 0x240 && (data.on_ground || self.p_speed) { self.p_meter += 3; }
                     } else {
-                        data.vel.x += 0x50;
+                        data.vel.x += m.turn;
                     }
                 }
                 button = buttons.right();
@@ -116,13 +232,16 @@ impl Player {
         if neutral && data.on_ground && self.slide_timer == 0 {
             self.p_speed = false;
             if data.vel.x > 0 {
-                data.vel.x -= 0x10;
+                data.vel.x -= m.friction;
                 if data.vel.x < 0 { data.vel.x = 0; }
             } else {
-                data.vel.x += 0x10;
+                data.vel.x += m.friction;
                 if data.vel.x > 0 { data.vel.x = 0; }
             }
         }
+        if ground == Terrain::Mud && data.vel.x.abs() > max_speed {
+            data.vel.x -= data.vel.x.signum() * m.friction;
+        }
         self.p_meter -= 1;
         if self.p_meter > 0x70 { self.p_speed = true; self.p_meter = 0x70; }
         if self.p_meter < 0 { self.p_meter = 0; }
@@ -179,15 +298,27 @@ impl Player {
             //data.sprites[1].len = 1;
         }
 
-        for i in crate::state().as_level().entities.inner.iter_mut() {
+        let level = crate::state().as_level();
+        for (slot,i) in level.entities.inner.iter_mut().enumerate() {
             // do not interact with self
             if &mut i.data as *mut _ == data as *mut _ { continue; }
             if data.intersects(&i.data) {
                 if !matches!(i.data.state, EntityState::Alive) { continue; }
+                if matches!(i.kind, EntityKind::Checkpoint(false)) {
+                    i.light();
+                    let feet = i.data.pos.y + i.data.radius.y;
+                    level.checkpoint = Some(Checkpoint {
+                        room: level.room,
+                        slot,
+                        pos: vec2(i.data.pos.x, feet - Self::STAND_RADIUS.y - 0x100),
+                    });
+                }
                 if matches!(i.kind, EntityKind::Tomato) {
                     if data.intersects(&i.data) {
                         if data.vel.y > 0 && i.data.pos.y - data.pos.y > 0x400 && self.can_interact() {
                             data.vel.y = -0x500;
+                            level.camera.shake(1, 6);
+                            level.defeated += 1;
                             i.data.state = EntityState::Squished;
                             i.data.anim_timer = 30;
                             i.data.sprites[0].frame = 3;
@@ -196,6 +327,7 @@ impl Player {
                                 .init_kick(i.data.pos);*/
                             i.data.state = EntityState::Dead;
                             i.data.vel = vec2(-0x40, -0x200);
+                            level.defeated += 1;
                         } else if !self.invulnerable() {
                             self.hurt(data);
                         }
@@ -207,58 +339,91 @@ impl Player {
 
         let old_x = data.vel.x;
 
-
-        data.physics();
-        unsafe {
-            if self.pose == PlayerPose::Sliding && old_x.abs() > 0x180 {
-                for i in 0..3 {
-                    if *data.sensors[i] == 3 {
-                        *data.sensors[i] = 0;
-                        data.vel.x = old_x;
-                        for v in 0..4 {
-                            //crate::state().as_level().particles.slot()
-                            //    .init_crumble(v, data.sensor_pos[i]);
-                        }
-                    }
+        let level = crate::state().as_level();
+        let fg = &mut level.fg;
+        data.physics(fg, &level.entities.inner);
+        if self.pose == PlayerPose::Sliding && old_x.abs() > 0x180 {
+            for i in 0..3 {
+                if fg.hit(data.sensor_pos[i] >> 12, Hit::Slide) {
+                    crate::state().as_level().camera.shake(2, 10);
+                    data.vel.x = old_x;
                 }
             }
-            if !self.invulnerable() {
-                for i in 3..6 {
-                    if *data.sensors[i] == 6 {
-                        self.hurt(data);
-                    }
+        }
+        if !self.invulnerable() {
+            for i in 3..6 {
+                if fg.get(data.sensor_pos[i] >> 12) == 6 {
+                    self.hurt(data);
                 }
             }
-            for (idx,i) in data.sensors.iter_mut().enumerate() {
-                match **i {
-                    0x10 => {
-                        **i = 0;
-                        crate::state().as_level().coins += 1;
-                        //crate::state().as_level().particles.slot()
-                        //    .init_sparkle(data.sensor_pos[idx]);
-                    },
-                    0x11 => {
-                        **i = 0;
-                    }
-                    0x12 => {
-                        **i = 0;
-                        crate::state().as_level().health += 1;
-                    }
-                    _ => {}
+        }
+        for idx in 0..6 {
+            let pos = data.sensor_pos[idx] >> 12;
+            match fg.get(pos) {
+                0x10 => {
+                    fg.set(pos, 0);
+                    crate::state().as_level().collect_gem(pos);
+                    //crate::state().as_level().particles.slot()
+                    //    .init_sparkle(data.sensor_pos[idx]);
+                },
+                0x11 => {
+                    fg.set(pos, 0);
+                    crate::state().as_level().consume(pos);
                 }
+                0x12 => {
+                    fg.set(pos, 0);
+                    crate::state().as_level().consume(pos);
+                    let l = crate::state().as_level();
+                    l.health = (l.health + 1).min(LevelState::MAX_HEALTH);
+                }
+                _ => {}
             }
         }
-        let fg = &mut crate::state().as_level().fg;
-
-        if unsafe { *fg.mut_ptr_at(data.pos / 256 / 16) == 0x0F } && buttons.up_edge() && data.on_ground {
-            crate::state().as_level().room += 1;
-            crate::state().as_level().init_flag = true;
+        let level = crate::state().as_level();
+        let (l,r) = (crate::data::WARP_OFFSET[level.room], crate::data::WARP_OFFSET[level.room+1]);
+        for warp in crate::data::WARPS[l..r].iter().filter(|c| c.contains(data.pos)) {
+            let enter = match warp.kind {
+                WarpKind::Door => buttons.up_edge() && data.on_ground,
+                WarpKind::Pipe => buttons.down_edge() && data.on_ground,
+                WarpKind::Exit => true,
+            };
+            if enter {
+                level.warp(warp.room, Some(warp.entry));
+                break;
+            }
         }
         let level_w = fg.width() as i32 * 16 * 256 - 0x1000;
         let level_h = fg.height() as i32 * 16 * 256;
         if data.pos.x < 0x1000 { data.pos.x = 0x1000; data.vel.x = 0; }
         if data.pos.x > level_w { data.pos.x = level_w; data.vel.x = 0; }
-        if data.pos.y > level_h { crate::state().as_level().init_flag = true; }
+        if data.pos.y > level_h { self.die(data, false); }
+
+        // Autoscroll drags the player along with the screen edges; being pushed
+        // into a wall or dropping off the bottom of the screen is fatal.
+        let camera = &crate::state().as_level().camera;
+        if camera.mode == ScrollMode::Auto {
+            let screen = Framebuffer::size() * 256;
+            let left = camera.pos.x * 256 + data.radius.x;
+            let right = camera.pos.x * 256 + screen.x - data.radius.x;
+            let mut crushed = false;
+            if data.pos.x < left {
+                data.pos.x = left;
+                data.vel.x = data.vel.x.max(0);
+                crushed = Self::solid_at(fg, data.pos + vec2(data.radius.x - 1, 0));
+            } else if data.pos.x > right {
+                data.pos.x = right;
+                data.vel.x = data.vel.x.min(0);
+                crushed = Self::solid_at(fg, data.pos - vec2(data.radius.x, 0));
+            }
+            if crushed {
+                self.die(data, true);
+            } else if data.pos.y - data.radius.y > camera.pos.y * 256 + screen.y {
+                self.die(data, false);
+            }
+        }
+    }
+    fn solid_at(fg: &foreground::Foreground, pos: Vec2<i32>) -> bool {
+        foreground::collision(fg.get(pos >> 12)).is_solid()
     }
     /*
     if data.blocked[1] || data.blocked[2] {
@@ -6,13 +6,86 @@ use crate::Framebuffer;
 pub struct Foreground {
     pub width: usize,
     pub height: usize,
-    pub blocks: [u8; 4096],
+    pub blocks: [u8; data::MAP_SIZE],
+    pub back: [u8; data::MAP_SIZE],
+    pub front: [u8; data::MAP_SIZE],
+    pub has_back: bool,
+    pub has_front: bool,
+    pub tileset: usize,
+    // Blocks beyond the left, right, top and bottom edges of the map
+    pub outside: [u8; 4],
+    // Tile drawn for each tile number, changed by tile animations
+    pub display: [u16; TILESET_SIZE],
+    // Tile drawn over the block above each tile number, or 0
+    pub tops: [u16; TILESET_SIZE],
+    pub tick: i32,
+    pub timers: [BlockTimer; 8],
 }
 
+pub struct BlockTimer {
+    pub kind: TimerKind,
+    pub pos: Vec2<i32>,
+    pub timer: i32,
+}
+
+#[derive(Copy,Clone,PartialEq,Eq)]
+pub enum TimerKind {
+    None,
+    Bump,
+    Crumble,
+}
+
+// Per-object override for the contents of an item box.
+pub struct BlockItem {
+    pub x: u16,
+    pub y: u16,
+    pub item: u8,
+}
+
+#[derive(Copy,Clone,PartialEq,Eq)]
+pub enum Hit {
+    Below,
+    Slide,
+    Stand,
+}
+
+const BUMP_HEIGHT: [i32; 9] = [0, 1, 3, 4, 5, 5, 4, 3, 1];
+const CRUMBLE_TIME: i32 = 30;
+pub const USED_BOX: u8 = 0x05;
+// One entry per possible block; the build rejects animations and tops on
+// tiles beyond it.
+const TILESET_SIZE: usize = 0x100;
+
 impl Foreground {
     pub fn init(&mut self, width: usize, height: usize) {
         self.width = width;
         self.height = height;
+        for i in self.timers.iter_mut() {
+            i.kind = TimerKind::None;
+        }
+        for (i,c) in self.display.iter_mut().enumerate() {
+            *c = i as u16;
+        }
+        self.tops = [0; TILESET_SIZE];
+        let (l,r) = (data::TILE_TOP_OFFSET[self.tileset], data::TILE_TOP_OFFSET[self.tileset+1]);
+        for i in data::TILE_TOPS[l..r].iter() {
+            self.tops[i.tile as usize] = i.top;
+        }
+    }
+    // Animated tiles only change what is drawn; `blocks` keeps the original
+    // tile so collision is unaffected.
+    fn animate(&mut self) {
+        self.tick += 1;
+        let (l,r) = (data::TILE_ANIM_OFFSET[self.tileset], data::TILE_ANIM_OFFSET[self.tileset+1]);
+        for anim in data::TILE_ANIMS[l..r].iter() {
+            self.display[anim.tile as usize] = anim.frame(self.tick);
+        }
+    }
+    pub fn tile(&self, block: usize) -> &'static [u8] {
+        self.tileset().tile(self.display.get(block).map_or(block, |&c| c as usize))
+    }
+    pub fn tileset(&self) -> &'static data::Tileset {
+        &data::TILESETS[self.tileset]
     }
     pub fn width(&self) -> usize {
         self.width
@@ -20,14 +93,128 @@ impl Foreground {
     pub fn height(&self) -> usize {
         self.height
     }
-    pub fn block_at(&self, pos: Vec2<i32>) -> usize {
-        pos.x as usize + pos.y as usize * self.width
+    fn index(&self, pos: Vec2<i32>) -> Option<usize> {
+        if self.in_bounds(pos) {
+            Some(pos.x as usize + pos.y as usize * self.width)
+        } else {
+            None
+        }
     }
     pub fn in_bounds(&self, pos: Vec2<i32>) -> bool {
-        self.width() > pos.x as usize && self.height() > pos.y as usize
+        pos.x >= 0 && pos.y >= 0 && (pos.x as usize) < self.width && (pos.y as usize) < self.height
     }
-    pub fn mut_ptr_at(&mut self, pos: Vec2<i32>) -> *mut u8 {
-        unsafe { self.blocks.as_mut_ptr().add(self.block_at(pos)) }
+    // Block at `pos`, or the outside block for the edge it lies past. Above
+    // and below take precedence in the corners.
+    pub fn get(&self, pos: Vec2<i32>) -> u8 {
+        match self.index(pos) {
+            Some(idx) => self.blocks[idx],
+            None if pos.y < 0 => self.outside[2],
+            None if pos.y >= self.height as i32 => self.outside[3],
+            None if pos.x < 0 => self.outside[0],
+            None => self.outside[1],
+        }
+    }
+    // Changes the block at `pos`; blocks outside the map can't be changed.
+    pub fn set(&mut self, pos: Vec2<i32>, block: u8) {
+        if let Some(idx) = self.index(pos) {
+            self.blocks[idx] = block;
+        }
+    }
+    fn get_layer(&self, layer: &[u8], pos: Vec2<i32>) -> u8 {
+        self.index(pos).map_or(0, |c| layer[c])
+    }
+    // Reacts to an entity hitting the block at `pos`. Returns true if the
+    // block did something.
+    pub fn hit(&mut self, pos: Vec2<i32>, hit: Hit) -> bool {
+        if !self.in_bounds(pos) { return false; }
+        let block = self.get(pos);
+        match (action(block), hit) {
+            (Action::Bump(item), Hit::Below) => {
+                self.set(pos, USED_BOX);
+                self.start_timer(TimerKind::Bump, pos, BUMP_HEIGHT.len() as i32 - 1);
+                let level = crate::state().as_level();
+                level.consume(pos);
+                let (l,r) = (data::BLOCK_ITEM_OFFSET[level.room], data::BLOCK_ITEM_OFFSET[level.room+1]);
+                let item = data::BLOCK_ITEMS[l..r].iter()
+                    .find(|c| vec2(c.x as i32, c.y as i32) == pos)
+                    .map(|c| c.item)
+                    .unwrap_or(item);
+                let above = pos - vec2(0, 1);
+                if self.in_bounds(above) && self.get(above) == 0 {
+                    self.set(above, item);
+                }
+                true
+            }
+            (Action::Break, Hit::Slide) => {
+                self.set(pos, 0);
+                self.debris(pos, block);
+                true
+            }
+            (Action::Crumble, Hit::Stand) => {
+                if !self.timers.iter().any(|c| c.kind == TimerKind::Crumble && c.pos == pos) {
+                    self.start_timer(TimerKind::Crumble, pos, CRUMBLE_TIME);
+                }
+                true
+            }
+            _ => false
+        }
+    }
+    // When every timer is busy, the one closest to running out finishes
+    // early to make room.
+    fn start_timer(&mut self, kind: TimerKind, pos: Vec2<i32>, timer: i32) {
+        let i = match self.timers.iter().position(|c| c.kind == TimerKind::None) {
+            Some(i) => i,
+            None => {
+                let (i, _) = self.timers.iter().enumerate().min_by_key(|(_,c)| c.timer).unwrap();
+                self.finish_timer(i);
+                i
+            }
+        };
+        self.timers[i] = BlockTimer { kind, pos, timer };
+    }
+    fn finish_timer(&mut self, i: usize) {
+        let t = &mut self.timers[i];
+        let (kind, pos) = (t.kind, t.pos);
+        t.kind = TimerKind::None;
+        if kind == TimerKind::Crumble {
+            let block = self.get(pos);
+            self.set(pos, 0);
+            self.debris(pos, block);
+        }
+    }
+    fn debris(&self, pos: Vec2<i32>, block: u8) {
+        let center = pos * 0x1000 + vec2(0x800, 0x800);
+        for v in 0..4 {
+            crate::state().as_level().particles.slot().init_crumble(v, center, block);
+        }
+    }
+    pub fn process(&mut self) {
+        self.animate();
+        for i in 0..self.timers.len() {
+            let t = &mut self.timers[i];
+            if t.kind == TimerKind::None { continue; }
+            t.timer -= 1;
+            if t.timer <= 0 { self.finish_timer(i); }
+        }
+    }
+    // Block and position within it to draw at a given pixel, taking bumped
+    // blocks into account.
+    fn sample(&self, pos: Vec2<i32>) -> (usize, Vec2<i32>) {
+        for t in self.timers.iter().filter(|c| c.kind == TimerKind::Bump) {
+            let shifted = pos + vec2(0, BUMP_HEIGHT[t.timer as usize]);
+            if shifted >> 4 == t.pos {
+                // Only where the bumped block is opaque, so whatever it
+                // spawned above stays visible
+                let (block, inner) = (self.get(t.pos) as usize, shifted & 0xF);
+                if self.tile(block)[(inner.x + inner.y * 16) as usize] != 0 {
+                    return (block, inner);
+                }
+            }
+            if pos >> 4 == t.pos {
+                return (0, pos & 0xF);
+            }
+        }
+        (self.get(pos >> 4) as usize, pos & 0xF)
     }
     pub fn render_old(&self, offset: Vec2<i32>, fb: &mut Framebuffer) {
         let block_pos = offset >> 4;
@@ -35,47 +222,59 @@ impl Foreground {
         for y in 0..=Framebuffer::size().y / 16 {
             for x in 0..=Framebuffer::size().x / 16 {
                 let pos = block_pos + vec2(x,y);
-                let block = self.blocks[self.block_at(pos)] as usize;
+                let block = self.get(pos) as usize;
                 if block > 0 {
-                    let gfx = &data::BLOCKS.data()[block*256..block*256+256];
+                    let gfx = self.tile(block);
                     for (i,px) in gfx.iter().enumerate() {
                         if *px > 0 {
                             let pos = vec2((x << 4) + (i as i32 & 0xF), (y << 4) + (i as i32 >> 4)) - inner;
                             let c = fb.pixel(pos).unwrap();
-                            *c = data::BLOCKS.pal()[*px as usize];
+                            *c = self.tileset().pal()[*px as usize];
                         }
                     }
                 }
             }
         }
     }
+    // Decorative layer drawn behind the main blocks.
+    pub fn render_back(&self, offset: Vec2<i32>, fb: &mut Framebuffer) {
+        if self.has_back { self.render_layer(&self.back, offset, fb); }
+    }
+    // Decorative layer drawn in front of entities.
+    pub fn render_front(&self, offset: Vec2<i32>, fb: &mut Framebuffer) {
+        if self.has_front { self.render_layer(&self.front, offset, fb); }
+    }
+    fn render_layer(&self, layer: &[u8], offset: Vec2<i32>, fb: &mut Framebuffer) {
+        let tiles = self.tileset();
+        for (pos,i) in fb.pixels() {
+            let pos = pos + offset;
+            let block = self.get_layer(layer, pos >> 4) as usize;
+            if block == 0 { continue; }
+            let inner = pos & 0x0F;
+            let px = self.tile(block)[inner.x as usize + inner.y as usize * 16] as usize;
+            if px != 0 {
+                *i = tiles.pal()[px];
+            }
+        }
+    }
     pub fn render(&self, offset: Vec2<i32>, fb: &mut Framebuffer) {
+        let tiles = self.tileset();
         for (pos,i) in fb.pixels() {
             //if pos.y == 0 { continue; }
             let pos = pos + offset;
-            /*if !self.in_bounds(pos >> 4) {
-                *i = 0x00000000;
-                continue;
-            }*/
-            let block = self.blocks[self.block_at(pos >> 4)] as usize;
-            let block_top = self.blocks[self.block_at((pos >> 4) + vec2(0,1))] as usize;
-            let block_top = if block_top >= 0x30 && block_top < 0x38 {
-                (block_top - 0x30 + 0x100) & 0x1F3
-            } else if block_top == 0x0F {
-                0x10F
-            } else {
-                0
-            };
-            let inner = pos & 0x0F;
-            let gfx = &data::BLOCKS.data()[block*256..];
-            let gfx2 = &data::BLOCKS.data()[block_top*256..];
+            let (block, inner) = self.sample(pos);
+            let below = self.get((pos >> 4) + vec2(0,1));
+            let block_top = self.tops[below as usize] as usize;
+            let gfx = self.tile(block);
+            let gfx2 = self.tile(block_top);
             let px = gfx[inner.x as usize + inner.y as usize * 16] as usize;
-            let px2 = gfx2[inner.x as usize + inner.y as usize * 16] as usize;
+            let top = pos & 0x0F;
+            let px2 = gfx2[top.x as usize + top.y as usize * 16] as usize;
             if px2 != 0 {
-                let color = data::BLOCKS.pal()[px2];
+                let color = tiles.pal()[px2];
                 *i = color;
             } else if px != 0 {
-                let color = data::BLOCKS.pal()[px];
+                let color = tiles.pal()[px];
                 *i = color;
             }
         }
@@ -101,16 +300,65 @@ impl Collision {
 pub fn collision(b: u8) -> Collision {
     use Collision::*;
     match b {
-        0x01 => Solid,
-        0x04 => Solid,
+        0x01 ..= 0x05 => Solid,
+        0x07 ..= 0x08 => Solid,
         0x10 => Gem,
         0x30 ..= 0x33 => Solid,
         0x40 ..= 0x43 => Solid,
         0x50 ..= 0x53 => Solid,
         0x60 ..= 0x63 => Solid,
         0x70 ..= 0x73 => Solid,
+        0xB0 ..= 0xB7 => Solid,
         0x34 ..= 0x37 => Semisolid,
         0x38 ..= 0x3A => Semisolid,
         _ => None
     }
 }
+
+pub enum Action {
+    None,
+    Bump(u8),
+    Break,
+    Crumble,
+}
+
+pub fn action(b: u8) -> Action {
+    use Action::*;
+    match b {
+        0x02 => Bump(0x10),
+        0x08 => Bump(0x12),
+        0x03 => Break,
+        0x07 => Crumble,
+        _ => None
+    }
+}
+
+#[derive(Copy,Clone,PartialEq,Eq)]
+pub enum Terrain {
+    Normal,
+    Ice,
+    Conveyor(i32),
+    Mud,
+    Water,
+}
+
+impl Terrain {
+    pub fn conveyor(&self) -> i32 {
+        match self {
+            Terrain::Conveyor(speed) => *speed,
+            _ => 0
+        }
+    }
+}
+
+pub fn terrain(b: u8) -> Terrain {
+    use Terrain::*;
+    match b {
+        0xB0 ..= 0xB3 => Ice,
+        0xB4 => Conveyor(-0x100),
+        0xB5 => Conveyor(0x100),
+        0xB6 ..= 0xB7 => Mud,
+        0xB8 ..= 0xBB => Water,
+        _ => Normal
+    }
+}
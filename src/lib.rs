@@ -1,4 +1,4 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 mod lz4;
 mod vec2;
@@ -9,13 +9,18 @@ mod controller;
 mod state;
 mod utils;
 mod entity;
+mod particle;
 mod foreground;
 mod background;
+mod camera;
+mod save;
+mod menu;
 
 use crate::framebuffer::Framebuffer;
 use crate::state::GameState;
 
 
+#[cfg(not(test))]
 #[panic_handler]
 unsafe fn handle_panic(_: &core::panic::PanicInfo) -> ! {
     // Where we're going, we don't need safety.
@@ -46,6 +51,38 @@ pub unsafe fn drw(b: u32) {
     STATE.run(&mut BUF, b);
 }
 
+// Music track the host should be playing, as an index into the manifest's
+// list of music names (in order of first use), or -1 for silence.
+#[no_mangle]
+pub unsafe fn mus() -> i32 {
+    STATE.music()
+}
+
+// Save data for the host to persist. `save_len` writes the current progress
+// to the buffer at `save_ptr` and returns its length; to restore, the host
+// copies a save of at most `save_cap` bytes there and calls `load` with its
+// length. `load` returns 0 and keeps the current progress if the save is
+// corrupt or from another version.
+#[no_mangle]
+pub unsafe fn save_ptr() -> *mut u8 {
+    save::SAVE.as_mut_ptr()
+}
+
+#[no_mangle]
+pub fn save_cap() -> usize {
+    save::SAVE_SIZE
+}
+
+#[no_mangle]
+pub unsafe fn save_len() -> usize {
+    STATE.progress.save(&mut save::SAVE)
+}
+
+#[no_mangle]
+pub unsafe fn load(len: usize) -> i32 {
+    STATE.progress.load(&save::SAVE[..len.min(save::SAVE_SIZE)]) as i32
+}
+
 #[no_mangle]
 pub static mut SND: [f32; 1024] = [0.0; 1024];
 
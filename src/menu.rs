@@ -0,0 +1,268 @@
+use crate::framebuffer::Framebuffer;
+use crate::controller::Buttons;
+use crate::data;
+use crate::vec2::{Vec2, vec2};
+use crate::state::Settings;
+
+#[derive(Clone, Copy)]
+pub enum Item {
+    Action(&'static [u8]),
+    // Opens another page of the same menu
+    Submenu(&'static [u8], usize),
+    // Returns to the page this one was opened from
+    Back(&'static [u8]),
+    Toggle(&'static [u8], bool),
+    // Label, value, min and max; changed with left and right
+    Slider(&'static [u8], i32, i32, i32),
+}
+
+impl Item {
+    fn label(&self) -> &'static [u8] {
+        match *self {
+            Item::Action(c) | Item::Submenu(c, _) | Item::Back(c) | Item::Toggle(c, _) | Item::Slider(c, ..) => c,
+        }
+    }
+}
+
+// What the owner of a menu has to act on. Items are referred to by index.
+pub enum Event {
+    Activate(usize),
+    Toggle(usize, bool),
+    Slide(usize, i32),
+}
+
+// Enough for the longest page, the title's level select: every room and BACK.
+const MAX_ITEMS: usize = if data::TITLE_MENU.len() + 1 > 8 { data::TITLE_MENU.len() + 1 } else { 8 };
+
+pub struct Menu {
+    pub page: usize,
+    pub selected: usize,
+    // Pages (and their selections) that submenus were opened from
+    stack: [(usize, usize); 4],
+    depth: usize,
+    held: i32,
+    held_dir: Vec2<i32>,
+    // Items on screen at once, and the first one shown
+    rows: usize,
+    scroll: usize,
+    // The current page, rebuilt by the owner every frame. Kept here rather
+    // than on the stack, which is tiny.
+    items: [Item; MAX_ITEMS],
+    len: usize,
+}
+
+impl Menu {
+    // Frames a direction is held before it repeats, and between repeats.
+    const REPEAT_DELAY: i32 = 20;
+    const REPEAT_RATE: i32 = 5;
+    const SPACING: i32 = 10;
+
+    pub fn init(&mut self, rows: usize) {
+        self.page = 0;
+        self.selected = 0;
+        self.depth = 0;
+        self.held = 0;
+        self.rows = rows.max(1);
+        self.scroll = 0;
+        self.len = 0;
+    }
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+    // Items past MAX_ITEMS are dropped.
+    pub fn push(&mut self, item: Item) {
+        if self.len < MAX_ITEMS {
+            self.items[self.len] = item;
+            self.len += 1;
+        }
+    }
+    fn items(&self) -> &[Item] {
+        &self.items[..self.len]
+    }
+    // Does nothing when submenus are already nested as deep as they can be,
+    // since BACK couldn't return from it.
+    pub fn open(&mut self, page: usize) {
+        if self.depth == self.stack.len() { return; }
+        self.stack[self.depth] = (self.page, self.selected);
+        self.depth += 1;
+        self.page = page;
+        self.selected = 0;
+    }
+    pub fn back(&mut self) {
+        if self.depth > 0 {
+            self.depth -= 1;
+            (self.page, self.selected) = self.stack[self.depth];
+        }
+    }
+    // Direction pressed this frame, repeating while held.
+    fn step(&mut self, b: Buttons) -> Vec2<i32> {
+        let dir = vec2(b.right() as i32 - b.left() as i32, b.down() as i32 - b.up() as i32);
+        if dir != self.held_dir {
+            self.held_dir = dir;
+            self.held = 0;
+        }
+        if dir == vec2(0, 0) { return dir; }
+        self.held += 1;
+        let repeat = self.held - Self::REPEAT_DELAY;
+        if self.held == 1 || (repeat > 0 && repeat % Self::REPEAT_RATE == 0) {
+            dir
+        } else {
+            vec2(0, 0)
+        }
+    }
+    pub fn update(&mut self, b: Buttons) -> Option<Event> {
+        let event = self.select(b);
+        // Keep the selection on screen
+        self.scroll = self.scroll.min(self.selected).max((self.selected + 1).saturating_sub(self.rows));
+        event
+    }
+    fn select(&mut self, b: Buttons) -> Option<Event> {
+        if self.len == 0 { return None; }
+        let step = self.step(b);
+        self.selected = (self.selected as i32 + step.y).rem_euclid(self.len as i32) as usize;
+
+        let i = self.selected;
+        match self.items[i] {
+            Item::Slider(_, value, min, max) if step.x != 0 => {
+                let next = (value + step.x).clamp(min, max);
+                if next != value { return Some(Event::Slide(i, next)); }
+            }
+            Item::Toggle(_, value) if step.x != 0 => return Some(Event::Toggle(i, !value)),
+            _ => {}
+        }
+        if b.b_edge() {
+            self.back();
+        } else if b.a_edge() || b.start_edge() {
+            match self.items[i] {
+                Item::Action(_) => return Some(Event::Activate(i)),
+                Item::Submenu(_, page) => self.open(page),
+                Item::Back(_) => self.back(),
+                Item::Toggle(_, value) => return Some(Event::Toggle(i, !value)),
+                Item::Slider(..) => {}
+            }
+        }
+        None
+    }
+    // Labels from `pos` down, values in a column to their right, and a
+    // cursor to the left of the selection. Dots mark items scrolled out of view.
+    pub fn render(&self, pos: Vec2<i32>, fb: &mut Framebuffer) {
+        let shown = self.scroll..self.len.min(self.scroll + self.rows);
+        if shown.start > 0 {
+            crate::utils::draw_text(data::BOLDFACE, b"...", vec2(pos.x + 16, pos.y - Self::SPACING), fb);
+        }
+        if shown.end < self.len {
+            let y = pos.y + self.rows as i32 * Self::SPACING;
+            crate::utils::draw_text(data::BOLDFACE, b"...", vec2(pos.x + 16, y), fb);
+        }
+        for (i,item) in self.items()[shown.clone()].iter().enumerate() {
+            let y = pos.y + i as i32 * Self::SPACING;
+            crate::utils::draw_text(data::BOLDFACE, item.label(), vec2(pos.x + 16, y), fb);
+            let value = vec2(pos.x + 16 + 8 * 14, y);
+            match *item {
+                Item::Toggle(_, on) => {
+                    let text: &[u8] = if on { b"ON" } else { b"OFF" };
+                    crate::utils::draw_text(data::BOLDFACE, text, value, fb);
+                }
+                Item::Slider(_, n, min, max) => {
+                    let left = if n > min { b'<' } else { b' ' };
+                    let right = if n < max { b'>' } else { b' ' };
+                    let n = n.max(0).min(99);
+                    let text = [left, b' ', (n / 10) as u8 + b'0', (n % 10) as u8 + b'0', b' ', right];
+                    crate::utils::draw_text(data::BOLDFACE, &text, value, fb);
+                }
+                _ => {}
+            }
+        }
+        let cursor = vec2(pos.x, pos.y + (self.selected - self.scroll) as i32 * Self::SPACING);
+        crate::utils::draw_text(data::BOLDFACE, b"-", cursor, fb);
+    }
+}
+
+// The options page, shared by the title screen and the pause menu.
+pub const OPTIONS: usize = 0x100;
+
+// Music options join this page once the host plays audio.
+pub fn options(settings: &Settings, menu: &mut Menu) {
+    menu.push(Item::Toggle(b"TIMER", settings.timer));
+    menu.push(Item::Back(b"BACK"));
+}
+
+pub fn set_option(settings: &mut Settings, event: Event) {
+    if let Event::Toggle(0, on) = event {
+        settings.timer = on;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn menu(len: usize, rows: usize) -> Menu {
+        let mut m: Menu = unsafe { core::mem::zeroed() };
+        m.init(rows);
+        for _ in 0..len {
+            m.push(Item::Action(b"ITEM"));
+        }
+        m
+    }
+    fn press(m: &mut Menu, held: &mut Buttons, pressed: u32) -> Option<Event> {
+        held.update(pressed);
+        m.update(*held)
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut b = Buttons::new();
+        let mut m = menu(3, 8);
+        press(&mut m, &mut b, Buttons::UP);
+        assert_eq!(m.selected, 2);
+        press(&mut m, &mut b, 0);
+        press(&mut m, &mut b, Buttons::DOWN);
+        assert_eq!(m.selected, 0);
+    }
+
+    #[test]
+    fn repeats_and_scrolls() {
+        let mut b = Buttons::new();
+        let mut m = menu(MAX_ITEMS, 4);
+        let frames = Menu::REPEAT_DELAY + Menu::REPEAT_RATE * 5;
+        for _ in 0..frames {
+            press(&mut m, &mut b, Buttons::DOWN);
+            assert!(m.selected >= m.scroll && m.selected < m.scroll + 4);
+        }
+        assert_eq!(m.selected, 1 + 5);
+        assert_eq!(m.scroll, 3);
+    }
+
+    #[test]
+    fn back_restores_selection() {
+        let mut b = Buttons::new();
+        let mut m = menu(0, 8);
+        m.push(Item::Action(b"START"));
+        m.push(Item::Submenu(b"OPTIONS", OPTIONS));
+        press(&mut m, &mut b, Buttons::DOWN);
+        press(&mut m, &mut b, Buttons::A);
+        assert_eq!((m.page, m.selected), (OPTIONS, 0));
+        press(&mut m, &mut b, Buttons::B);
+        assert_eq!((m.page, m.selected), (0, 1));
+    }
+
+    #[test]
+    fn drops_items_past_capacity() {
+        let m = menu(MAX_ITEMS + 4, 8);
+        assert_eq!(m.items().len(), MAX_ITEMS);
+    }
+
+    #[test]
+    fn refuses_to_nest_too_deep() {
+        let mut m = menu(0, 8);
+        for page in 1..=m.stack.len() + 1 {
+            m.open(page);
+        }
+        assert_eq!(m.page, m.stack.len());
+        for _ in 0..m.stack.len() {
+            m.back();
+        }
+        assert_eq!(m.page, 0);
+    }
+}
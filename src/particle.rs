@@ -0,0 +1,91 @@
+use crate::vec2::{Vec2, vec2};
+use crate::Framebuffer;
+
+pub struct ParticleSet {
+    pub inner: [Particle; 32],
+    pub next: usize,
+}
+
+pub struct Particle {
+    pub kind: ParticleKind,
+    pub pos: Vec2<i32>,
+    pub vel: Vec2<i32>,
+    pub timer: i32,
+    pub block: u8,
+    pub quarter: u8,
+}
+
+pub enum ParticleKind {
+    None,
+    Crumble,
+}
+
+impl ParticleSet {
+    pub fn init(&mut self) {
+        for i in self.inner.iter_mut() {
+            i.kind = ParticleKind::None;
+        }
+        self.next = 0;
+    }
+    // Overwrites the oldest particle if all slots are in use.
+    pub fn slot(&mut self) -> &mut Particle {
+        let idx = self.next;
+        self.next = (self.next + 1) % self.inner.len();
+        &mut self.inner[idx]
+    }
+    pub fn process(&mut self) {
+        for i in self.inner.iter_mut() {
+            i.process();
+        }
+    }
+    pub fn render(&self, camera: Vec2<i32>, fb: &mut Framebuffer) {
+        for i in self.inner.iter() {
+            i.render(camera, fb);
+        }
+    }
+}
+
+impl Particle {
+    // A quarter (`v` in 0..4) of a broken block, flying outwards from `pos`.
+    pub fn init_crumble(&mut self, v: usize, pos: Vec2<i32>, block: u8) {
+        let dir = vec2((v & 1) as i32 * 2 - 1, (v >> 1) as i32);
+        self.kind = ParticleKind::Crumble;
+        self.pos = pos + dir * 0x400 - vec2(0, 0x400);
+        self.vel = vec2(dir.x * 0x100, -0x400 + dir.y * 0x180);
+        self.timer = 60;
+        self.block = block;
+        self.quarter = v as u8;
+    }
+    pub fn process(&mut self) {
+        match self.kind {
+            ParticleKind::None => {},
+            ParticleKind::Crumble => {
+                self.pos += self.vel;
+                self.vel.y += 0x30;
+                self.timer -= 1;
+                if self.timer == 0 { self.kind = ParticleKind::None; }
+            }
+        }
+    }
+    pub fn render(&self, camera: Vec2<i32>, fb: &mut Framebuffer) {
+        match self.kind {
+            ParticleKind::None => {},
+            ParticleKind::Crumble => {
+                let tiles = crate::state().as_level().fg.tileset();
+                let gfx = tiles.tile(self.block as usize);
+                let pal = tiles.pal();
+                let corner = vec2(self.quarter as i32 & 1, self.quarter as i32 >> 1) * 8;
+                let origin = (self.pos >> 8) - camera - vec2(4, 4);
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let src = corner + vec2(x, y);
+                        let px = gfx[(src.x + src.y * 16) as usize];
+                        if px != 0 {
+                            fb.pixel(origin + vec2(x, y)).map(|c| *c = pal[px as usize]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::data;
+use crate::state::Progress;
+
+// Save layout, all single bytes unless noted:
+//   "MNTY" magic, version, level count n,
+//   n x (completed, best gems, best time in frames (4 bytes)),
+//   room count m, m x (best split, gold split (4 bytes each)),
+//   overworld node, settings flags (timer),
+//   Fletcher-16 checksum of everything before it (2 bytes).
+// Multi-byte values are little endian.
+const MAGIC: &[u8; 4] = b"MNTY";
+const VERSION: u8 = 1;
+const LEVEL_BYTES: usize = 6;
+const ROOM_BYTES: usize = 8;
+// Room for the most levels and rooms a save can list, so saves from larger builds load too
+pub const SAVE_SIZE: usize = 11 + (LEVEL_BYTES + ROOM_BYTES) * 255;
+
+// Shared with the host: filled by `save_len`, or written by the host before `load`.
+pub static mut SAVE: [u8; SAVE_SIZE] = [0; SAVE_SIZE];
+
+fn checksum(bytes: &[u8]) -> u16 {
+    let (mut a, mut b) = (0u16, 0u16);
+    for &i in bytes {
+        a = (a + i as u16) % 255;
+        b = (b + a) % 255;
+    }
+    b << 8 | a
+}
+
+impl Progress {
+    // Writes the save into `out`, returning its length.
+    pub fn save(&self, out: &mut [u8; SAVE_SIZE]) -> usize {
+        out[..4].copy_from_slice(MAGIC);
+        out[4] = VERSION;
+        out[5] = data::LEVEL_COUNT as u8;
+        let mut len = 6;
+        for i in 0..data::LEVEL_COUNT {
+            out[len] = self.completed[i] as u8;
+            out[len+1] = self.best_gems[i];
+            out[len+2..len+6].copy_from_slice(&self.best_time[i].to_le_bytes());
+            len += LEVEL_BYTES;
+        }
+        out[len] = data::ROOM_COUNT as u8;
+        len += 1;
+        for i in 0..data::ROOM_COUNT {
+            out[len..len+4].copy_from_slice(&self.best_splits[i].to_le_bytes());
+            out[len+4..len+8].copy_from_slice(&self.gold_splits[i].to_le_bytes());
+            len += ROOM_BYTES;
+        }
+        out[len] = self.node as u8;
+        out[len+1] = self.settings.timer as u8;
+        len += 2;
+        let sum = checksum(&out[..len]);
+        out[len..len+2].copy_from_slice(&sum.to_le_bytes());
+        len + 2
+    }
+    // Restores a save written by `save`. Anything malformed is rejected as a
+    // whole, leaving progress untouched. Saves from builds with a different
+    // number of levels keep the levels both have in common.
+    pub fn load(&mut self, bytes: &[u8]) -> bool {
+        if bytes.len() < 11 || bytes[..4] != *MAGIC || bytes[4] != VERSION {
+            return false;
+        }
+        let count = bytes[5] as usize;
+        let rooms_at = 6 + count * LEVEL_BYTES;
+        let rooms = match bytes.get(rooms_at) {
+            Some(&c) => c as usize,
+            None => return false,
+        };
+        let len = rooms_at + 1 + rooms * ROOM_BYTES + 2;
+        if bytes.len() < len + 2 { return false; }
+        let sum = u16::from_le_bytes([bytes[len], bytes[len+1]]);
+        if checksum(&bytes[..len]) != sum { return false; }
+        let node = bytes[len-2] as usize;
+        if node >= data::NODES.len() { return false; }
+
+        *self = Progress::new();
+        for i in 0..count.min(data::LEVEL_COUNT) {
+            let entry = &bytes[6 + i*LEVEL_BYTES..];
+            self.completed[i] = entry[0] != 0;
+            self.best_gems[i] = entry[1];
+            self.best_time[i] = u32::from_le_bytes([entry[2], entry[3], entry[4], entry[5]]);
+        }
+        for i in 0..rooms.min(data::ROOM_COUNT) {
+            let entry = &bytes[rooms_at + 1 + i*ROOM_BYTES..];
+            self.best_splits[i] = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+            self.gold_splits[i] = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+        }
+        self.node = node;
+        self.settings.timer = bytes[len-1] & 1 != 0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::boxed::Box;
+
+    fn progress() -> Progress {
+        let mut p = Progress::new();
+        p.completed[0] = true;
+        p.best_gems[0] = 42;
+        p.best_time[0] = 12345;
+        p.best_splits[0] = 600;
+        p.gold_splits[0] = 550;
+        p.settings.timer = true;
+        p
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buf = Box::new([0; SAVE_SIZE]);
+        let len = progress().save(&mut buf);
+        let mut p = Progress::new();
+        assert!(p.load(&buf[..len]));
+        assert!(p.completed[0]);
+        assert_eq!(p.best_gems[0], 42);
+        assert_eq!(p.best_time[0], 12345);
+        assert_eq!((p.best_splits[0], p.gold_splits[0]), (600, 550));
+        assert!(p.settings.timer);
+    }
+
+    #[test]
+    fn rejects_damaged_saves() {
+        let mut buf = Box::new([0; SAVE_SIZE]);
+        let len = progress().save(&mut buf);
+        let mut p = Progress::new();
+        for cut in 0..len {
+            assert!(!p.load(&buf[..cut]), "loaded a save cut to {} bytes", cut);
+        }
+        for i in 0..len {
+            let mut damaged = buf.clone();
+            damaged[i] ^= 0x10;
+            assert!(!p.load(&damaged[..len]), "loaded a save with byte {} changed", i);
+        }
+        assert!(!p.completed[0]);
+    }
+}
@@ -0,0 +1,54 @@
+use crate::framebuffer::Framebuffer;
+use crate::controller::Buttons;
+use crate::data;
+use crate::vec2::vec2;
+use crate::menu::{Menu, Item, Event};
+use super::Progress;
+
+pub struct GameOverState {
+    pub init_flag: bool,
+    pub menu: Menu,
+    pub timer: i32,
+}
+
+impl GameOverState {
+    // Frames before the menu accepts input, so a held button doesn't skip it.
+    const DELAY: i32 = 60;
+
+    pub fn init(&mut self) {
+        self.menu.init(2);
+        self.timer = 0;
+    }
+    pub fn run(&mut self, fb: &mut Framebuffer, b: Buttons) {
+        if self.init_flag {
+            self.init_flag = false;
+            self.init();
+        }
+        if self.timer < Self::DELAY { self.timer += 1; }
+
+        for (_,px) in fb.pixels() {
+            *px = 0xFF000000;
+        }
+        let x = (Framebuffer::size().x - 72) / 2;
+        crate::utils::draw_text(data::BOLDFACE, b"GAME OVER", vec2(x, 64), fb);
+        if self.timer < Self::DELAY { return; }
+
+        self.menu.clear();
+        self.menu.push(Item::Action(b"CONTINUE"));
+        self.menu.push(Item::Action(b"QUIT TO TITLE"));
+        let event = self.menu.update(b);
+        self.menu.render(vec2(96, 88), fb);
+
+        if let Some(Event::Activate(i)) = event {
+            // NOTE: self destroyed
+            let s = crate::state();
+            if i == 0 {
+                // Completed levels are kept, so this picks up from the last save
+                s.progress.lives = Progress::START_LIVES;
+                s.enter_overworld();
+            } else {
+                s.enter_title();
+            }
+        }
+    }
+}
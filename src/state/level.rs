@@ -5,96 +5,351 @@ use crate::framebuffer::Framebuffer;
 use crate::controller::Buttons;
 use crate::data::{self, DataDef};
 use crate::entity::{EntityKind, EntityEntry, EntitySet};
-//use crate::particle::ParticleSet;
-use crate::state::GameStateId;
+use crate::particle::ParticleSet;
+use crate::camera::Camera;
+use crate::menu::{self, Menu, Item, Event};
+
+// How a level was played, shown on the results screen.
+#[derive(Clone, Copy)]
+pub struct Stats {
+    pub gems: i32,
+    // Frames spent in the level, not counting pauses
+    pub time: i32,
+    pub damage: i32,
+    pub defeated: i32,
+}
+
+// Where the player respawns after dying, set by touching a checkpoint.
+pub struct Checkpoint {
+    pub room: usize,
+    pub slot: usize,
+    pub pos: Vec2<i32>,
+}
 
 pub struct LevelState {
     pub fg: Foreground,
     pub bg: Background,
     pub entities: EntitySet,
-    //pub particles: ParticleSet,
-    pub camera: Vec2<i32>,
+    pub particles: ParticleSet,
+    pub camera: Camera,
     pub init_flag: bool,
     pub reset_flag: bool,
+    pub level: usize,
     pub room: usize,
+    pub entry: Option<Vec2<i32>>,
+    pub checkpoint: Option<Checkpoint>,
+    // Only attempts from the level's first room count towards records
+    pub started_at_first_room: bool,
     pub coins: i32,
+    pub time: i32,
+    pub damage: i32,
+    pub defeated: i32,
     pub health: i32,
+    pub banner_timer: i32,
+    pub paused: bool,
+    pub pause: Menu,
+    pub death_timer: i32,
+    // Boxes and pickups used up this attempt, one bit per COLLECTIBLES entry
+    pub collected: [u32; (data::COLLECTIBLE_COUNT + 31) / 32],
+    // Level time on leaving each room this attempt, indexed like MAPS
+    pub splits: [u32; data::ROOM_COUNT],
+    pub last_split: i32,
+    pub split_delta: Option<i32>,
+    pub split_gold: bool,
+    pub split_timer: i32,
 }
 
-
-
 impl LevelState {
+    // Frames from dying until the room restarts, the last FADE_TIME fading out.
+    pub const DEATH_TIME: i32 = 120;
+    pub const MAX_HEALTH: i32 = 5;
+    const FADE_TIME: i32 = 30;
+    const GEMS_PER_LIFE: i32 = 50;
+    // Frames a split comparison stays in the HUD
+    const SPLIT_TIME: i32 = 120;
+
     pub fn init(&mut self) {
-        if self.room >= data::LEVEL_COUNT {
+        if !data::LEVELS[self.level].contains(self.room) {
             return;
         }
         let map = &data::MAPS[self.room];
+        self.bg.level = self.level;
+        self.bg.vertical = map.scroll == data::ScrollMode::Vertical;
+        let size = map.width as usize * map.height as usize;
         unsafe {
+            self.fg.tileset = map.tileset;
+            self.fg.outside = map.outside;
             self.fg.init(map.width as _, map.height as _);
-            crate::copy_fwd(map.data().as_ptr(), self.fg.blocks.as_mut_ptr(), self.fg.blocks.len());
+            crate::copy_fwd(map.data().as_ptr(), self.fg.blocks.as_mut_ptr(), size);
+            self.fg.has_back = map.back.is_some();
+            if let Some(back) = map.back() {
+                crate::copy_fwd(back.as_ptr(), self.fg.back.as_mut_ptr(), size);
+            }
+            self.fg.has_front = map.front.is_some();
+            if let Some(front) = map.front() {
+                crate::copy_fwd(front.as_ptr(), self.fg.front.as_mut_ptr(), size);
+            }
         }
         let (l,r) = (data::ENTITY_OFFSET[self.room], data::ENTITY_OFFSET[self.room+1]);
         self.entities.init_with(&data::ENTITY_LIST[l..r]);
-        //self.particles.init();
+        self.particles.init();
         let e = &mut self.entities.inner;
         e[31].init(1);
-        e[31].data.pos = map.start_pos;
+        e[31].data.pos = self.entry.unwrap_or(map.start_pos);
         //e[2].init(2);
         //e[2].data.pos = vec2(0x4000, 0x4000);
 
         if self.reset_flag {
             self.coins = 0;
+            self.time = 0;
+            self.damage = 0;
+            self.defeated = 0;
+            self.splits = [0; data::ROOM_COUNT];
+            self.last_split = 0;
+            self.split_timer = 0;
             self.health = 3;
+            self.banner_timer = 120;
+            self.checkpoint = None;
+            self.collected = [0; (data::COLLECTIBLE_COUNT + 31) / 32];
             self.reset_flag = false;
         }
+        // Reloading a room mustn't bring back what was already collected
+        let (l,r) = (data::COLLECTIBLE_OFFSET[self.room], data::COLLECTIBLE_OFFSET[self.room+1]);
+        let collected = &self.collected;
+        for i in (l..r).filter(|&c| collected[c / 32] & 1 << (c % 32) != 0) {
+            let idx = data::COLLECTIBLES[i] as usize;
+            let used = match crate::foreground::action(self.fg.blocks[idx]) {
+                crate::foreground::Action::Bump(_) => crate::foreground::USED_BOX,
+                _ => 0,
+            };
+            self.fg.blocks[idx] = used;
+        }
+        if let Some(c) = &self.checkpoint {
+            if c.room == self.room {
+                self.entities.inner[c.slot].light();
+            }
+        }
+        self.camera.init(self.room, &self.entities.inner[31].data, &self.fg);
+    }
+    // Moves to another room, entering at `entry` or the room's start position.
+    pub fn warp(&mut self, room: usize, entry: Option<Vec2<i32>>) {
+        if room > self.room && self.splits[self.room] == 0 {
+            self.split();
+        }
+        self.room = room;
+        self.entry = entry;
+        self.init_flag = true;
+    }
+    // Remembers that the box or pickup at `pos` in the current room is used up.
+    pub fn consume(&mut self, pos: Vec2<i32>) {
+        let (l,r) = (data::COLLECTIBLE_OFFSET[self.room], data::COLLECTIBLE_OFFSET[self.room+1]);
+        let idx = (pos.x as usize + pos.y as usize * self.fg.width()) as u32;
+        if let Ok(i) = data::COLLECTIBLES[l..r].binary_search(&idx) {
+            self.collected[(l + i) / 32] |= 1 << ((l + i) % 32);
+        }
+    }
+    // Records the speedrun split for leaving the current room.
+    fn split(&mut self) {
+        let progress = &mut crate::state().progress;
+        let (delta, gold) = progress.split(self.room, self.time, self.time - self.last_split);
+        self.splits[self.room] = self.time as u32;
+        self.last_split = self.time;
+        // Level time only lines up with the best splits on a full run
+        self.split_delta = if self.started_at_first_room { delta } else { None };
+        self.split_gold = gold;
+        self.split_timer = Self::SPLIT_TIME;
+    }
+    // Restarts from the last checkpoint, or from where the current room was entered.
+    pub fn respawn(&mut self) {
+        if let Some(c) = &self.checkpoint {
+            self.room = c.room;
+            self.entry = Some(c.pos);
+        }
+        self.init_flag = true;
     }
     pub fn run(&mut self, fb: &mut Framebuffer, b: Buttons) {
-        if self.room >= data::LEVEL_COUNT {
+        let level = &data::LEVELS[self.level];
+        if !level.contains(self.room) {
+            // NOTE: self destroyed
             let s = crate::state();
-            s.id = GameStateId::Title;
-            s.as_title().init_flag = true;
+            if self.room == level.first_room + level.rooms {
+                let stats = self.stats();
+                let best = if self.started_at_first_room {
+                    s.progress.complete(self.level, &stats)
+                } else {
+                    (false, false)
+                };
+                // Splits from a partial run can't be compared with full ones
+                if self.started_at_first_room && best.1 {
+                    let rooms = level.first_room..level.first_room + level.rooms;
+                    s.progress.best_splits[rooms.clone()].copy_from_slice(&self.splits[rooms]);
+                }
+                s.enter_results(self.level, stats, best);
+            } else {
+                s.enter_overworld();
+            }
             return;
         }
         if self.init_flag {
             self.init_flag = false;
+            self.paused = false;
+            self.death_timer = 0;
             self.init();
         }
 
+        if self.paused {
+            self.render(fb);
+            self.pause_menu(fb, b);
+            return;
+        }
+        if b.start_edge() && self.death_timer == 0 {
+            self.paused = true;
+            self.pause.init(8);
+        }
+
+        self.time += 1;
+
         //if b.left() { self.camera.x -= 4; }
         //if b.right() { self.camera.x += 4; }
         //if b.up() { self.camera.y -= 4; }
         //if b.down() { self.camera.y += 4; }
-        //self.particles.process();
+        self.bg.process();
+        self.fg.process();
+        self.particles.process();
         self.entities.process();
 
         let followed_slot = 31;
+        if self.death_timer == 0 {
+            self.camera.update(self.room, &self.entities.inner[followed_slot].data, &self.fg, b);
+        }
+        if self.banner_timer > 0 { self.banner_timer -= 1; }
+        if self.split_timer > 0 { self.split_timer -= 1; }
 
-        let e = &self.entities.inner[followed_slot];
-        let pivot = e.data.visual_pos() - Framebuffer::size()/2;
-
+        self.render(fb);
+        if self.death_timer > 0 {
+            self.death_timer -= 1;
+            crate::utils::fade(fb, 256 * self.death_timer.min(Self::FADE_TIME) / Self::FADE_TIME);
+            if self.death_timer == 0 {
+                self.lose_life();
+            }
+        }
+    }
+    pub fn stats(&self) -> Stats {
+        Stats {
+            gems: self.coins,
+            time: self.time,
+            damage: self.damage,
+            defeated: self.defeated,
+        }
+    }
+    // Gems stay collected through respawns, so each counts toward a life once.
+    pub fn collect_gem(&mut self, pos: Vec2<i32>) {
+        self.consume(pos);
+        self.coins += 1;
+        if self.coins % Self::GEMS_PER_LIFE == 0 {
+            crate::state().progress.lives += 1;
+        }
+    }
+    // After the death sequence: back to the last checkpoint with full health,
+    // or game over when out of lives.
+    fn lose_life(&mut self) {
+        let s = crate::state();
+        s.progress.lives -= 1;
+        if s.progress.lives <= 0 {
+            // NOTE: self destroyed
+            s.enter_game_over();
+            return;
+        }
+        self.health = 3;
+        self.respawn();
+    }
+    fn render(&self, fb: &mut Framebuffer) {
+        self.bg.render(self.camera.view(), fb);
+        self.fg.render_back(self.camera.view(), fb);
+        self.fg.render(self.camera.view(), fb);
 
-        self.camera += (pivot - self.camera).zip(
-            e.data.vel,
-            |c,v| c.signum() * (c.abs() - 4).max(0)
-        );
-        self.camera.x = self.camera.x.max(0).min(self.fg.width()  as i32 * 16 - Framebuffer::size().x);
-        self.camera.y = self.camera.y.max(6).min(self.fg.height() as i32 * 16 - Framebuffer::size().y - 6);
+        self.entities.render(self.camera.view(), fb);
+        self.particles.render(self.camera.view(), fb);
+        self.fg.render_front(self.camera.view(), fb);
 
+        static mut COINS_TEXT: [u8; 8] = *b"GEMS 000";
+        let s = unsafe { &mut COINS_TEXT };
+        s[5] = (self.coins / 100 % 10) as u8 + b'0';
+        s[6] = (self.coins / 10 % 10) as u8 + b'0';
+        s[7] = (self.coins % 10) as u8 + b'0';
+        crate::utils::draw_text(data::BOLDFACE, s, vec2(8,8), fb);
 
-        //self.particles.render(self.camera, fb);
+        let h = b"HEALTH @@@@@";
+        crate::utils::draw_text(data::BOLDFACE, &h[..self.health.clamp(0, Self::MAX_HEALTH) as usize + 7], vec2(8,16), fb);
 
-        self.bg.render(self.camera, fb);
-        self.fg.render(self.camera, fb);
+        let lives = crate::state().progress.lives.min(99);
+        let text = [b'L', b'I', b'V', b'E', b'S', b' ', (lives / 10) as u8 + b'0', (lives % 10) as u8 + b'0'];
+        crate::utils::draw_text(data::BOLDFACE, &text, vec2(8,24), fb);
 
-        self.entities.render(self.camera, fb);
+        if crate::state().progress.settings.timer {
+            self.render_timer(fb);
+        }
 
-        static mut COINS_TEXT: [u8; 7] = *b"GEMS 00";
-        let s = unsafe { &mut COINS_TEXT };
-        s[5] = (self.coins / 10) as u8 + b'0';
-        s[6] = (self.coins % 10) as u8 + b'0';
-        crate::utils::draw_text(data::BOLDFACE, s, vec2(8,8), fb);
+        if self.banner_timer > 0 {
+            let level = &data::LEVELS[self.level];
+            let number = [level.world + b'0', b'-', level.number + b'0'];
+            crate::utils::draw_text(data::BOLDFACE, &number, vec2((Framebuffer::size().x - 24) / 2, 64), fb);
+            let x = (Framebuffer::size().x - 8 * level.name.len() as i32) / 2;
+            crate::utils::draw_text(data::BOLDFACE, level.name, vec2(x, 76), fb);
+        }
+    }
+    // Level time in the top right, with the last split compared to the best.
+    fn render_timer(&self, fb: &mut Framebuffer) {
+        let right = Framebuffer::size().x - 8;
+        let mut text = [0; 7];
+        crate::utils::format_time(self.time, &mut text);
+        crate::utils::draw_text(data::BOLDFACE, &text, vec2(right - 56, 8), fb);
+        if self.split_timer == 0 { return; }
+        if let Some(delta) = self.split_delta {
+            crate::utils::format_time(delta.abs(), &mut text);
+            let sign = if delta < 0 { b"-" } else { b"+" };
+            crate::utils::draw_text(data::BOLDFACE, sign, vec2(right - 64, 16), fb);
+            crate::utils::draw_text(data::BOLDFACE, &text, vec2(right - 56, 16), fb);
+        }
+        if self.split_gold {
+            crate::utils::draw_text(data::BOLDFACE, b"GOLD", vec2(right - 32, 24), fb);
+        }
+    }
+    fn build_pause_menu(&mut self) {
+        let m = &mut self.pause;
+        m.clear();
+        if m.page == menu::OPTIONS {
+            menu::options(&crate::state().progress.settings, m);
+        } else {
+            m.push(Item::Action(b"RESUME"));
+            m.push(Item::Action(b"RESTART ROOM"));
+            m.push(Item::Submenu(b"OPTIONS", menu::OPTIONS));
+            m.push(Item::Action(b"QUIT TO TITLE"));
+        }
+    }
+    // Drawn over the frozen level while paused.
+    fn pause_menu(&mut self, fb: &mut Framebuffer, b: Buttons) {
+        if b.start_edge() {
+            self.paused = false;
+            return;
+        }
+        self.build_pause_menu();
+        match (self.pause.page, self.pause.update(b)) {
+            (menu::OPTIONS, Some(event)) => menu::set_option(&mut crate::state().progress.settings, event),
+            (_, Some(Event::Activate(0))) => self.paused = false,
+            // Back to where the room was entered, keeping gems and health
+            (_, Some(Event::Activate(1))) => self.init_flag = true,
+            (_, Some(Event::Activate(_))) => {
+                // NOTE: self destroyed
+                crate::state().enter_title();
+                return;
+            }
+            _ => {}
+        }
 
-        let h = b"HEALTH @@@@@";
-        crate::utils::draw_text(data::BOLDFACE, &h[..self.health as usize + 7], vec2(8,16), fb);
+        crate::utils::fade(fb, 128);
+        crate::utils::draw_text(data::BOLDFACE, b"PAUSED", vec2((Framebuffer::size().x - 48) / 2, 56), fb);
+        self.build_pause_menu();
+        self.pause.render(vec2(96, 76), fb);
     }
 }
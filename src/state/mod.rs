@@ -1,26 +1,100 @@
 mod title;
 mod level;
+mod overworld;
+mod game_over;
+mod results;
+
+pub use level::{Checkpoint, LevelState, Stats};
 
 use core::mem::ManuallyDrop;
 use crate::framebuffer::Framebuffer;
 use crate::controller::Buttons;
+use crate::data;
 
 pub struct GameState {
     pub buttons: Buttons,
     pub id: GameStateId,
-    pub data: GameStateData
+    pub data: GameStateData,
+    pub progress: Progress,
 }
 
 pub enum GameStateId {
     Init,
     Title,
-    Level
+    Overworld,
+    Level,
+    GameOver,
+    Results,
 }
 
 pub union GameStateData {
     init: (),
     title: ManuallyDrop<title::TitleState>,
-    level: ManuallyDrop<level::LevelState>
+    overworld: ManuallyDrop<overworld::OverworldState>,
+    level: ManuallyDrop<level::LevelState>,
+    game_over: ManuallyDrop<game_over::GameOverState>,
+    results: ManuallyDrop<results::ResultsState>,
+}
+
+// Everything that outlives a single game state.
+pub struct Progress {
+    pub completed: [bool; data::LEVEL_COUNT],
+    // Most gems held when finishing each level
+    pub best_gems: [u8; data::LEVEL_COUNT],
+    // Fastest finish of each level in frames, or 0 if never finished
+    pub best_time: [u32; data::LEVEL_COUNT],
+    // Per room: the level time on leaving it in the best finish, and the
+    // fastest it has ever been cleared. 0 if not yet recorded.
+    pub best_splits: [u32; data::ROOM_COUNT],
+    pub gold_splits: [u32; data::ROOM_COUNT],
+    pub node: usize,
+    pub settings: Settings,
+    // Not saved; every session starts with START_LIVES
+    pub lives: i32,
+}
+
+pub struct Settings {
+    pub timer: bool,
+}
+
+impl Progress {
+    pub const START_LIVES: i32 = 3;
+    pub const fn new() -> Self {
+        Self {
+            completed: [false; data::LEVEL_COUNT],
+            best_gems: [0; data::LEVEL_COUNT],
+            best_time: [0; data::LEVEL_COUNT],
+            best_splits: [0; data::ROOM_COUNT],
+            gold_splits: [0; data::ROOM_COUNT],
+            node: data::START_NODE,
+            settings: Settings { timer: false },
+            lives: Self::START_LIVES,
+        }
+    }
+    // Records leaving `room` at level time `time` after a segment of `segment`
+    // frames. Returns the difference to the best finish's split, if there is
+    // one, and whether the segment is the fastest yet.
+    pub fn split(&mut self, room: usize, time: i32, segment: i32) -> (Option<i32>, bool) {
+        let best = self.best_splits[room];
+        let delta = if best == 0 { None } else { Some(time - best as i32) };
+        let gold = self.gold_splits[room] == 0 || (segment as u32) < self.gold_splits[room];
+        if gold { self.gold_splits[room] = segment as u32; }
+        (delta, gold)
+    }
+    // Marks a level finished, returning whether the gems and time are new bests.
+    pub fn complete(&mut self, level: usize, stats: &Stats) -> (bool, bool) {
+        self.completed[level] = true;
+        let gems = stats.gems.min(255) as u8;
+        let best_gems = gems > self.best_gems[level];
+        if best_gems { self.best_gems[level] = gems; }
+        let time = stats.time as u32;
+        let best_time = self.best_time[level] == 0 || time < self.best_time[level];
+        if best_time { self.best_time[level] = time; }
+        (best_gems, best_time)
+    }
+    pub fn unlocked(&self, path: &data::Route) -> bool {
+        path.unlock.map_or(true, |c| self.completed[c])
+    }
 }
 
 impl GameState {
@@ -28,7 +102,8 @@ impl GameState {
         Self {
             buttons: Buttons::new(),
             id: GameStateId::Init,
-            data: GameStateData { init: () }
+            data: GameStateData { init: () },
+            progress: Progress::new(),
         }
     }
     pub fn as_level(&mut self) -> &mut level::LevelState {
@@ -37,21 +112,82 @@ impl GameState {
     pub fn as_title(&mut self) -> &mut title::TitleState {
         unsafe { &mut self.data.title }
     }
+    pub fn as_overworld(&mut self) -> &mut overworld::OverworldState {
+        unsafe { &mut self.data.overworld }
+    }
+    pub fn as_game_over(&mut self) -> &mut game_over::GameOverState {
+        unsafe { &mut self.data.game_over }
+    }
+    pub fn as_results(&mut self) -> &mut results::ResultsState {
+        unsafe { &mut self.data.results }
+    }
+    // NOTE: destroys the current state
+    pub fn enter_title(&mut self) {
+        self.id = GameStateId::Title;
+        self.as_title().init_flag = true;
+    }
+    // NOTE: destroys the current state
+    pub fn enter_results(&mut self, level: usize, stats: Stats, best: (bool, bool)) {
+        self.id = GameStateId::Results;
+        let r = self.as_results();
+        r.init_flag = true;
+        r.level = level;
+        r.stats = stats;
+        r.best = best;
+    }
+    // NOTE: destroys the current state
+    pub fn enter_game_over(&mut self) {
+        self.id = GameStateId::GameOver;
+        self.as_game_over().init_flag = true;
+    }
+    // NOTE: destroys the current state
+    pub fn enter_overworld(&mut self) {
+        self.id = GameStateId::Overworld;
+        self.as_overworld().init_flag = true;
+    }
+    // NOTE: destroys the current state
+    pub fn enter_level(&mut self, level: usize, room: usize) {
+        self.id = GameStateId::Level;
+        let l = self.as_level();
+        l.init_flag = true;
+        l.reset_flag = true;
+        l.level = level;
+        l.room = room;
+        l.started_at_first_room = room == data::LEVELS[level].first_room;
+        l.entry = None;
+    }
+    pub fn music(&mut self) -> i32 {
+        match self.id {
+            GameStateId::Level => {
+                let level = self.as_level().level;
+                data::LEVELS[level].music as i32
+            }
+            _ => -1
+        }
+    }
     pub fn run(&mut self, fb: &mut Framebuffer, b: u32) {
         self.buttons.update(b);
         let b = self.buttons;
         match self.id {
             GameStateId::Init => {
-                crate::data::init();
-                self.id = GameStateId::Title;
-                self.as_title().init_flag = true;
+                data::init();
+                self.enter_title();
             }
             GameStateId::Title => {
                 self.as_title().run(fb,b);
             },
+            GameStateId::Overworld => {
+                self.as_overworld().run(fb,b);
+            },
             GameStateId::Level => {
                 self.as_level().run(fb,b);
             },
+            GameStateId::GameOver => {
+                self.as_game_over().run(fb,b);
+            },
+            GameStateId::Results => {
+                self.as_results().run(fb,b);
+            },
         }
     }
 }
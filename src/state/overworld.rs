@@ -0,0 +1,117 @@
+use crate::framebuffer::Framebuffer;
+use crate::controller::Buttons;
+use crate::data;
+use crate::vec2::{Vec2, vec2};
+
+pub struct OverworldState {
+    pub init_flag: bool,
+    pub node: usize,
+    pub target: Option<usize>,
+    pub pos: Vec2<i32>,
+    pub anim_timer: i32,
+}
+
+impl OverworldState {
+    const WALK_SPEED: i32 = 2;
+
+    pub fn init(&mut self) {
+        self.node = crate::state().progress.node;
+        self.target = None;
+        self.pos = data::NODES[self.node].pos;
+        self.anim_timer = 0;
+    }
+    // Unlocked path from the current node that leads roughly in `dir`.
+    fn path_towards(&self, dir: Vec2<i32>) -> Option<usize> {
+        let progress = &crate::state().progress;
+        data::ROUTES.iter()
+            .filter(|c| progress.unlocked(c))
+            .filter_map(|c| c.other(self.node))
+            .find(|&other| {
+                let delta = data::NODES[other].pos - data::NODES[self.node].pos;
+                if delta.x.abs() >= delta.y.abs() {
+                    delta.x.signum() == dir.x
+                } else {
+                    delta.y.signum() == dir.y
+                }
+            })
+    }
+    pub fn run(&mut self, fb: &mut Framebuffer, b: Buttons) {
+        if self.init_flag {
+            self.init_flag = false;
+            self.init();
+        }
+
+        self.anim_timer += 1;
+        if let Some(target) = self.target {
+            let delta = data::NODES[target].pos - self.pos;
+            self.pos += delta.map(|c| c.signum() * c.abs().min(Self::WALK_SPEED));
+            if self.pos == data::NODES[target].pos {
+                self.node = target;
+                self.target = None;
+                crate::state().progress.node = target;
+            }
+        } else {
+            let dir = if b.left_edge() {
+                vec2(-1, 0)
+            } else if b.right_edge() {
+                vec2(1, 0)
+            } else if b.up_edge() {
+                vec2(0, -1)
+            } else if b.down_edge() {
+                vec2(0, 1)
+            } else {
+                vec2(0, 0)
+            };
+            if dir != vec2(0, 0) {
+                self.target = self.path_towards(dir);
+            }
+        }
+
+        self.render(fb);
+
+        if self.target.is_none() && (b.start_edge() || b.a_edge()) {
+            if let Some(level) = data::NODES[self.node].level {
+                // NOTE: self destroyed
+                crate::state().enter_level(level, data::LEVELS[level].first_room);
+                return;
+            }
+        }
+    }
+    fn render(&self, fb: &mut Framebuffer) {
+        for (_,px) in fb.pixels() {
+            *px = 0xFF3F7F3F;
+        }
+        let progress = &crate::state().progress;
+        for path in data::ROUTES.iter() {
+            let color = if progress.unlocked(path) { 0xFF9FCFFF } else { 0xFF2F5F2F };
+            let (from, to) = (data::NODES[path.from].pos, data::NODES[path.to].pos);
+            let len = (to - from).map(|c| c.abs()).reduce(|x,y| x.max(y));
+            for i in (0..len).step_by(4) {
+                let pos = from + (to - from) * i / len;
+                for d in vec2(0, 0).product_range(vec2(2, 2)) {
+                    fb.pixel(pos + d).map(|c| *c = color);
+                }
+            }
+        }
+        for node in data::NODES.iter() {
+            let color = match node.level {
+                Some(c) if progress.completed[c] => 0xFF3FCFFF,
+                Some(_) => 0xFF3F3FDF,
+                None => 0xFF9FCFFF,
+            };
+            for d in vec2(-4, -4).product_range(vec2(4, 4)) {
+                fb.pixel(node.pos + d).map(|c| *c = color);
+            }
+        }
+        let bob = if self.target.is_some() { self.anim_timer / 4 & 1 } else { 0 };
+        crate::utils::draw_sprite(data::TOOTHPASTE, 0x10, self.pos - vec2(16, 28 + bob), fb);
+        crate::utils::draw_sprite(data::TOOTHPASTE, 0, self.pos - vec2(16, 28 + bob), fb);
+
+        if let Some(level) = data::NODES[self.node].level.filter(|_| self.target.is_none()) {
+            let level = &data::LEVELS[level];
+            let number = [level.world + b'0', b'-', level.number + b'0', b' '];
+            crate::utils::draw_text(data::BOLDFACE, &number, vec2(8, 8), fb);
+            crate::utils::draw_text(data::BOLDFACE, level.name, vec2(40, 8), fb);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use crate::framebuffer::Framebuffer;
+use crate::controller::Buttons;
+use crate::data;
+use crate::vec2::vec2;
+use super::Stats;
+
+pub struct ResultsState {
+    pub init_flag: bool,
+    pub level: usize,
+    pub stats: Stats,
+    // Whether the gems and time beat the previous bests
+    pub best: (bool, bool),
+    pub timer: i32,
+}
+
+// Three digits of `n`, right aligned.
+fn digits(n: i32) -> [u8; 3] {
+    let n = n.max(0).min(999);
+    let mut out = [(n / 100) as u8 + b'0', (n / 10 % 10) as u8 + b'0', (n % 10) as u8 + b'0'];
+    for i in 0..2 {
+        if out[i] != b'0' { break; }
+        out[i] = b' ';
+    }
+    out
+}
+
+impl ResultsState {
+    // Frames before each line appears, and before input is accepted.
+    const LINE_DELAY: i32 = 20;
+
+    pub fn init(&mut self) {
+        self.timer = 0;
+    }
+    pub fn run(&mut self, fb: &mut Framebuffer, b: Buttons) {
+        if self.init_flag {
+            self.init_flag = false;
+            self.init();
+        }
+        self.timer += 1;
+
+        for (_,px) in fb.pixels() {
+            *px = 0xFF222222;
+        }
+        let level = &data::LEVELS[self.level];
+        let number = [level.world + b'0', b'-', level.number + b'0', b' '];
+        let x = (Framebuffer::size().x - 8 * (level.name.len() as i32 + 4)) / 2;
+        crate::utils::draw_text(data::BOLDFACE, &number, vec2(x, 24), fb);
+        crate::utils::draw_text(data::BOLDFACE, level.name, vec2(x + 32, 24), fb);
+        crate::utils::draw_text(data::BOLDFACE, b"COURSE CLEAR", vec2((Framebuffer::size().x - 96) / 2, 40), fb);
+
+        let gems = digits(self.stats.gems);
+        let total = digits(level.gems());
+        let gems = [gems[0], gems[1], gems[2], b'/', total[0], total[1], total[2]];
+        let mut time = [0; 7];
+        crate::utils::format_time(self.stats.time, &mut time);
+        let lines: [(&[u8], &[u8], bool); 4] = [
+            (b"GEMS", &gems, self.best.0),
+            (b"TIME", &time, self.best.1),
+            (b"DAMAGE", &digits(self.stats.damage), false),
+            (b"ENEMIES", &digits(self.stats.defeated), false),
+        ];
+        for (i,(label, value, best)) in lines.iter().enumerate() {
+            if self.timer < (i as i32 + 1) * Self::LINE_DELAY { break; }
+            let y = 64 + i as i32 * 12;
+            crate::utils::draw_text(data::BOLDFACE, label, vec2(64, y), fb);
+            crate::utils::draw_text(data::BOLDFACE, value, vec2(144, y), fb);
+            if *best {
+                crate::utils::draw_text(data::BOLDFACE, b"NEW BEST", vec2(208, y), fb);
+            }
+        }
+
+        if self.timer < (lines.len() as i32 + 1) * Self::LINE_DELAY { return; }
+        crate::utils::draw_text(data::BOLDFACE, b"PRESS A", vec2((Framebuffer::size().x - 56) / 2, 132), fb);
+        if b.start_edge() || b.a_edge() {
+            // NOTE: self destroyed
+            let s = crate::state();
+            if data::NODES.iter().any(|c| c.level == Some(self.level)) {
+                s.enter_overworld();
+            } else if self.level + 1 < data::LEVEL_COUNT {
+                // Levels not on the overworld lead straight into the next one
+                s.enter_level(self.level + 1, data::LEVELS[self.level + 1].first_room);
+            } else {
+                s.enter_title();
+            }
+        }
+    }
+}
@@ -1,17 +1,39 @@
 use crate::framebuffer::Framebuffer;
 use crate::controller::Buttons;
 use crate::data;
-use crate::vec2::{Vec2, vec2};
+use crate::vec2::vec2;
+use crate::menu::{self, Menu, Item, Event};
 
+const LEVEL_SELECT: usize = 1;
 
 pub struct TitleState {
     pub init_flag: bool,
-    pub selected: usize
+    pub menu: Menu,
 }
 
 impl TitleState {
     pub fn init(&mut self) {
-        self.selected = 0;
+        // Rows from y=32 down, leaving room for the scroll marker
+        self.menu.init(13);
+    }
+    fn build_menu(&mut self) {
+        let m = &mut self.menu;
+        m.clear();
+        match m.page {
+            LEVEL_SELECT => {
+                // Each entry picks a room directly
+                for entry in data::TITLE_MENU.iter() {
+                    m.push(Item::Action(entry.text));
+                }
+                m.push(Item::Back(b"BACK"));
+            }
+            menu::OPTIONS => menu::options(&crate::state().progress.settings, m),
+            _ => {
+                m.push(Item::Action(b"START"));
+                m.push(Item::Submenu(b"LEVEL SELECT", LEVEL_SELECT));
+                m.push(Item::Submenu(b"OPTIONS", menu::OPTIONS));
+            }
+        }
     }
     pub fn run(&mut self, fb: &mut Framebuffer, b: Buttons) {
         if self.init_flag {
@@ -19,40 +41,33 @@ impl TitleState {
             self.init();
         }
 
-        if b.up_edge() {
-            self.selected -= 1;
-        } else if b.down_edge() {
-            self.selected += 1;
-        }
-        self.selected = self.selected.rem_euclid(data::LEVEL_COUNT);
-
-        for (i,px) in fb.pixels() {
+        for (_,px) in fb.pixels() {
             *px = 0xFF222222;
         }
         let text = include_bytes!(concat!(env!("OUT_DIR"), "/title.txt"));
         let x = (Framebuffer::size().x - 8 * text.len() as i32)/2;
         crate::utils::draw_text(data::BOLDFACE, text, vec2(x,8), fb);
 
-        crate::utils::draw_text(data::BOLDFACE, b"1-1-1 FRENCH FRY FIELDS", vec2(32, 32), fb);
-        crate::utils::draw_text(data::BOLDFACE, b"1-1-2", vec2(32, 40), fb);
-        crate::utils::draw_text(data::BOLDFACE, b"1-1-3", vec2(32, 48), fb);
-        crate::utils::draw_text(data::BOLDFACE, b"1-1-4", vec2(32, 56), fb);
-
-        crate::utils::draw_text(data::BOLDFACE, b"-", vec2(16, 32 + self.selected as i32 * 8), fb);
-
-
-        if b.start_edge() || b.right_edge() {
-            // NOTE: self destroyed
-            unsafe {
-                use super::GameStateId;
-                let v = self.selected;
-                let st = crate::state();
-                st.id = GameStateId::Level;
-                st.as_level().init_flag = true;
-                st.as_level().reset_flag = true;
-                st.as_level().room = v;
+        self.build_menu();
+        let page = self.menu.page;
+        let st = crate::state();
+        match (page, self.menu.update(b)) {
+            (menu::OPTIONS, Some(event)) => menu::set_option(&mut st.progress.settings, event),
+            (_, Some(Event::Activate(i))) => {
+                // NOTE: self destroyed
+                st.progress.lives = super::Progress::START_LIVES;
+                if page == LEVEL_SELECT {
+                    let entry = &data::TITLE_MENU[i];
+                    st.enter_level(entry.level, entry.room);
+                } else {
+                    st.enter_overworld();
+                }
                 return;
             }
+            _ => {}
         }
+        // Rebuilt so page changes and new values show this frame
+        self.build_menu();
+        self.menu.render(vec2(16, 32), fb);
     }
 }
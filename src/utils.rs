@@ -20,3 +20,43 @@ pub fn draw_symbol(data: &[u8], pos: Vec2<i32>, fb: &mut Framebuffer) {
     }
 }
 
+
+// Writes a frame count as minutes, seconds and hundredths: "M:SS.CC".
+// Anything from ten minutes up shows as 9:59.99.
+pub fn format_time(frames: i32, out: &mut [u8; 7]) {
+    if frames >= 10 * 3600 {
+        *out = *b"9:59.99";
+        return;
+    }
+    let hundredths = frames % 60 * 100 / 60;
+    let seconds = frames / 60 % 60;
+    let minutes = frames / 3600;
+    *out = [
+        minutes as u8 + b'0', b':',
+        (seconds / 10) as u8 + b'0', (seconds % 10) as u8 + b'0', b'.',
+        (hundredths / 10) as u8 + b'0', (hundredths % 10) as u8 + b'0',
+    ];
+}
+
+// Darkens the whole framebuffer; `level` 256 leaves it as is, 0 is black.
+pub fn fade(fb: &mut Framebuffer, level: i32) {
+    let level = level.max(0).min(256) as u32;
+    for (_,px) in fb.pixels() {
+        let c = *px;
+        let r = (c & 0xFF) * level >> 8;
+        let g = (c >> 8 & 0xFF) * level >> 8;
+        let b = (c >> 16 & 0xFF) * level >> 8;
+        *px = 0xFF000000 | b << 16 | g << 8 | r;
+    }
+}
+
+// Draws a 32x32 frame of a sprite sheet.
+pub fn draw_sprite(d: DataDef, frame: usize, pos: Vec2<i32>, fb: &mut Framebuffer) {
+    let data = &d.data()[frame * 32 * 32..];
+    let pal = d.pal();
+    for i in 0usize..32 * 32 {
+        if data[i] != 0 {
+            fb.pixel(pos + vec2((i & 0x1F) as i32, (i >> 5) as i32)).map(|c| *c = pal[data[i] as usize]);
+        }
+    }
+}